@@ -1,8 +1,10 @@
+use common::{
+    policy::check_policy, simulation::check_simulation, transaction::finalize_transaction,
+};
 use errors::ActionError;
 use solana_sdk::{
-    instruction::AccountMeta, instruction::Instruction, message::Message, pubkey, pubkey::Pubkey,
+    instruction::AccountMeta, instruction::Instruction, pubkey, pubkey::Pubkey,
     signature::Keypair, signer::Signer, system_program::ID as SYSTEM_PROGRAM_ID,
-    transaction::Transaction,
 };
 use std::str::FromStr;
 use structs::{CreateInstructionArgs, SquadsMetadata};
@@ -70,13 +72,18 @@ pub mod create {
         );
 
         let instructions = vec![instruction];
-        let message = Message::new(&instructions, None);
-        let transaction = Transaction::new_unsigned(message);
-
-        Ok(ActionTransaction {
-            transaction,
-            message: Some("Multisig successfully created!".to_string()),
-        })
+        let finalized_transaction =
+            finalize_transaction(&instructions, &account_pubkey, &ctx.env.rpc_url).await?;
+
+        check_policy(
+            &finalized_transaction.transaction,
+            &account_pubkey,
+            &[SQUADS_PROGRAM_ID, SYSTEM_PROGRAM_ID],
+            "SQUADS_ALLOWED_PROGRAM_IDS",
+        )?;
+        check_simulation(&finalized_transaction.transaction, &ctx.env.rpc_url).await?;
+
+        Ok(finalized_transaction.into_action_transaction("Multisig successfully created!"))
     }
 
     fn get_create(ctx: Context<CreateAction>) -> Result<ActionMetadata> {
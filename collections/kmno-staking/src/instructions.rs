@@ -6,14 +6,14 @@ use spl_associated_token_account::get_associated_token_address;
 use spl_token::ID as TOKEN_PROGRAM_ID;
 
 const KMNO_MINT_ADDRESS: Pubkey = pubkey!("KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS");
-const KMNO_STAKING_PROGRAM: Pubkey = pubkey!("FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr");
+pub const KMNO_STAKING_PROGRAM: Pubkey = pubkey!("FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr");
 const KMNO_FARM_STATE: Pubkey = pubkey!("2sFZDpBn4sA42uNbAD6QzQ98rPSmqnPyksYe6SJKVvay");
 const KMNO_FARM_VAULT: Pubkey = pubkey!("5xpGE38rm4ZqAgQiuocqkw6cM6Cwrwvx6BVJk6i2oKhv");
 const KMNO_FARM_VAULT_AUTHORITY: Pubkey = pubkey!("Ec6MuWtpvFcVyMsp7vipKCg1CMkKrWHZpWPdnJF16G57");
 const KMNO_SCOPE_PRICES: Pubkey = pubkey!("FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr");
 const RENT_PROGRAM: Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
 const BASE_SEED_USER_STATE: &[u8; 4] = b"user";
-const KMNO_DECIMALS: u32 = 6; 
+pub const KMNO_DECIMALS: u8 = 6;
 const KMNO_UNSTAKE_DECIMALS: u32 = 6 * 4;
 
 pub fn initializer_user_instruction(account_pubkey: Pubkey, user_state: Pubkey) -> Instruction {
@@ -42,7 +42,7 @@ pub fn initializer_user_instruction(account_pubkey: Pubkey, user_state: Pubkey)
     return initializer_user_instruction;
 }
 
-pub async fn stake_instruction(account_pubkey: Pubkey, user_amount: f32, rpc: String) -> Vec<Instruction> {
+pub async fn stake_instruction(account_pubkey: Pubkey, amount: u64, rpc: String) -> Vec<Instruction> {
     let kmno_ata = get_associated_token_address(&account_pubkey, &KMNO_MINT_ADDRESS);
 
     let (user_state, _user_state_bump) = Pubkey::find_program_address(
@@ -54,9 +54,6 @@ pub async fn stake_instruction(account_pubkey: Pubkey, user_amount: f32, rpc: St
         &KMNO_STAKING_PROGRAM,
     );
 
-    let decimals_result = 10u64.pow(KMNO_DECIMALS);
-    let amount: u64 = user_amount as u64 * decimals_result;
-
     let stake_args = StakeInstructionArgs { amount };
     let stake_serialized_args = bincode::serialize(&stake_args).expect("Error serializing args");
 
@@ -104,12 +101,13 @@ pub async fn stake_instruction(account_pubkey: Pubkey, user_amount: f32, rpc: St
     return instructions;
 }
 
-pub fn unstake_instruction(account_pubkey: Pubkey, user_state: Pubkey, user_amount: f32) -> Instruction {
-    let decimals_result = 10u128.pow(KMNO_UNSTAKE_DECIMALS);
-    let amount: u128 = user_amount as u128 * decimals_result;
+pub fn unstake_instruction(account_pubkey: Pubkey, user_state: Pubkey, amount: u64) -> Instruction {
+    // Stake shares are scaled beyond the token's own decimals
+    let scale = 10u128.pow(KMNO_UNSTAKE_DECIMALS - KMNO_DECIMALS as u32);
+    let stake_shares_scaled: u128 = amount as u128 * scale;
 
     let unstake_args = UnstakeInstructionArgs {
-        stake_shares_scaled: amount as u128,
+        stake_shares_scaled,
     };
     let unstake_serialized_args =
         bincode::serialize(&unstake_args).expect("Error serializing args");
@@ -136,7 +134,7 @@ pub fn unstake_instruction(account_pubkey: Pubkey, user_state: Pubkey, user_amou
     return unstake_instruction;
 }
 
-pub fn withdraw_unstaked_deposits_instruction(account_pubkey: Pubkey, user_amount: f32) -> Vec<Instruction> {
+pub fn withdraw_unstaked_deposits_instruction(account_pubkey: Pubkey, amount: u64) -> Vec<Instruction> {
     let kmno_ata = get_associated_token_address(&account_pubkey, &KMNO_MINT_ADDRESS);
     let (user_state, _user_state_bump) = Pubkey::find_program_address(
         &[
@@ -165,7 +163,7 @@ pub fn withdraw_unstaked_deposits_instruction(account_pubkey: Pubkey, user_amoun
         AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
     ];
 
-    let unstake_instruction = unstake_instruction(account_pubkey,  user_state, user_amount);
+    let unstake_instruction = unstake_instruction(account_pubkey,  user_state, amount);
     let withdraw_instruction =
         Instruction::new_with_bytes(KMNO_STAKING_PROGRAM, &unstake_data, stake_accounts);
 
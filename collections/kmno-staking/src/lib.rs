@@ -1,6 +1,12 @@
+use common::{
+    amount::DecimalAmount, policy::check_policy, simulation::check_simulation,
+    transaction::finalize_transaction,
+};
 use errors::ActionError;
-use instructions::{stake_instruction, withdraw_unstaked_deposits_instruction};
-use solana_sdk::{message::Message, pubkey, pubkey::Pubkey, transaction::Transaction};
+use instructions::{
+    stake_instruction, withdraw_unstaked_deposits_instruction, KMNO_DECIMALS, KMNO_STAKING_PROGRAM,
+};
+use solana_sdk::{pubkey, pubkey::Pubkey, system_program};
 use std::str::FromStr;
 use znap::prelude::*;
 use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
@@ -22,7 +28,7 @@ pub mod kmno_staking {
             .or_else(|_| Err(Error::from(ActionError::InvalidAccountPublicKey)))?;
         
         let method = ctx.query.method.clone();
-        let amount = ctx.query.amount;
+        let amount = DecimalAmount::parse(&ctx.query.amount)?.to_base_units(KMNO_DECIMALS)?;
         let rpc = ctx.env.rpc_url.clone();
 
         let create_send_ata_instruction = create_associated_token_account_idempotent(
@@ -42,13 +48,24 @@ pub mod kmno_staking {
 
         instructions.extend_from_slice(&staking_instructions);
 
-        let message = Message::new(&instructions, None);
-        let transaction = Transaction::new_unsigned(message);
+        let finalized_transaction =
+            finalize_transaction(&instructions, &account_pubkey, &ctx.env.rpc_url).await?;
 
-        Ok(ActionTransaction {
-            transaction,
-            message: Some(format!("{} successfully completed", method.to_uppercase())),
-        })
+        check_policy(
+            &finalized_transaction.transaction,
+            &account_pubkey,
+            &[
+                system_program::id(),
+                TOKEN_PROGRAM_ID,
+                spl_associated_token_account::ID,
+                KMNO_STAKING_PROGRAM,
+            ],
+            "KMNO_ALLOWED_PROGRAM_IDS",
+        )?;
+        check_simulation(&finalized_transaction.transaction, &ctx.env.rpc_url).await?;
+
+        Ok(finalized_transaction
+            .into_action_transaction(&format!("{} successfully completed", method.to_uppercase())))
     }
 }
 
@@ -69,5 +86,5 @@ pub mod kmno_staking {
         parameter = { label = "Amount", name = "amount"  }
     },
 )]
-#[query(amount: f32, method: String)]
+#[query(amount: String, method: String)]
 pub struct StakingAction;
@@ -0,0 +1,157 @@
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use std::{env, str::FromStr};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::invoice::{verify_request, Invoice};
+use crate::jupiter_api::{JupiterSwapProvider, DEFAULT_JUPITER_API_URL};
+use crate::platform_fee::PlatformFee;
+use crate::swap_provider::SwapProvider;
+
+pub const SEND_MINT_ADDRESS: Pubkey = pubkey!("SENDdRQtYMWaQrBroBrJ2Q53fgVuq95CV9UPGEvpCxa");
+const DEFAULT_SLIPPAGE_BPS: u16 = 50;
+const DEFAULT_MAX_PRICE_IMPACT_PCT: f64 = 5.0;
+const MAX_SLIPPAGE_BPS: u16 = 10_000;
+const DEFAULT_MAX_PRIORITY_FEE_LAMPORTS: u64 = 100_000;
+
+// Per-deployment settings, read from the environment
+pub struct PayConfig {
+    pub output_mint: Pubkey,
+    pub slippage_bps: u16,
+    pub max_price_impact_pct: f64,
+    // Quotes and builds the swaps, Jupiter at JUPITER_API_URL
+    pub swap_provider: Box<dyn SwapProvider>,
+    // Cap on the priority fee added to every transaction
+    pub max_priority_fee_lamports: u64,
+    pub platform_fee: Option<PlatformFee>,
+    // Invoices are enabled when both are set
+    invoice_secret: Option<String>,
+    invoice_api_key: Option<String>,
+}
+
+impl PayConfig {
+    pub fn from_env() -> Result<PayConfig> {
+        let output_mint = match env::var("PAY_OUTPUT_MINT") {
+            Ok(mint) => Pubkey::from_str(&mint)
+                .or_else(|_| Err(Error::from(ActionError::InvalidOutputMintPublicKey)))?,
+            Err(_) => SEND_MINT_ADDRESS,
+        };
+
+        let slippage_bps = match env::var("PAY_SLIPPAGE_BPS") {
+            Ok(slippage_bps) => validate_slippage_bps(
+                slippage_bps
+                    .parse::<u16>()
+                    .or_else(|_| Err(Error::from(ActionError::InvalidSlippage)))?,
+            )?,
+            Err(_) => DEFAULT_SLIPPAGE_BPS,
+        };
+
+        let max_price_impact_pct = match env::var("PAY_MAX_PRICE_IMPACT_PCT") {
+            Ok(max_price_impact_pct) => validate_max_price_impact_pct(
+                max_price_impact_pct
+                    .parse::<f64>()
+                    .or_else(|_| Err(Error::from(ActionError::InvalidPriceImpactLimit)))?,
+            )?,
+            Err(_) => DEFAULT_MAX_PRICE_IMPACT_PCT,
+        };
+
+        let jupiter_api_url =
+            env::var("JUPITER_API_URL").unwrap_or(DEFAULT_JUPITER_API_URL.to_string());
+
+        let max_priority_fee_lamports = match env::var("PAY_MAX_PRIORITY_FEE_LAMPORTS") {
+            Ok(max_priority_fee_lamports) => max_priority_fee_lamports
+                .parse::<u64>()
+                .or_else(|_| Err(Error::from(ActionError::InvalidPriorityFeeLimit)))?,
+            Err(_) => DEFAULT_MAX_PRIORITY_FEE_LAMPORTS,
+        };
+
+        Ok(PayConfig {
+            output_mint,
+            slippage_bps,
+            max_price_impact_pct,
+            swap_provider: Box::new(JupiterSwapProvider::new(&jupiter_api_url)),
+            max_priority_fee_lamports,
+            platform_fee: PlatformFee::from_env()?,
+            invoice_secret: env::var("INVOICE_SECRET").ok(),
+            invoice_api_key: env::var("INVOICE_API_KEY").ok(),
+        })
+    }
+
+    // Verifies the invoice carried by a pay link, if any
+    pub fn invoice(&self, token: &Option<String>, receiver: &Pubkey) -> Result<Option<Invoice>> {
+        let token = match token {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        let invoice_secret = self
+            .invoice_secret
+            .as_ref()
+            .ok_or(Error::from(ActionError::InvoicesDisabled))?;
+
+        Invoice::verify(token, invoice_secret, receiver).map(Some)
+    }
+
+    // Only the merchant holding INVOICE_API_KEY can issue invoices, by signing the request with it
+    pub fn check_invoice_request(
+        &self,
+        request: &str,
+        timestamp: u64,
+        signature: &str,
+    ) -> Result<()> {
+        let invoice_api_key = match (&self.invoice_secret, &self.invoice_api_key) {
+            (Some(_), Some(invoice_api_key)) => invoice_api_key,
+            _ => return Err(Error::from(ActionError::InvoicesDisabled)),
+        };
+
+        verify_request(invoice_api_key, request, timestamp, signature)
+    }
+
+    pub fn sign_invoice(&self, invoice: &Invoice) -> Result<String> {
+        let invoice_secret = self
+            .invoice_secret
+            .as_ref()
+            .ok_or(Error::from(ActionError::InvoicesDisabled))?;
+
+        invoice.sign(invoice_secret)
+    }
+
+    // The mint received by the receiver, either from the query or the deployment default
+    pub fn output_mint(&self, requested_output_mint: &Option<String>) -> Result<Pubkey> {
+        match requested_output_mint {
+            Some(mint) => Pubkey::from_str(mint)
+                .or_else(|_| Err(Error::from(ActionError::InvalidOutputMintPublicKey))),
+            None => Ok(self.output_mint),
+        }
+    }
+
+    pub fn slippage_bps(&self, requested_slippage_bps: Option<u16>) -> Result<u16> {
+        match requested_slippage_bps {
+            Some(slippage_bps) => validate_slippage_bps(slippage_bps),
+            None => Ok(self.slippage_bps),
+        }
+    }
+
+    pub fn max_price_impact_pct(&self, requested_max_price_impact_pct: Option<f64>) -> Result<f64> {
+        match requested_max_price_impact_pct {
+            Some(max_price_impact_pct) => validate_max_price_impact_pct(max_price_impact_pct),
+            None => Ok(self.max_price_impact_pct),
+        }
+    }
+}
+
+fn validate_slippage_bps(slippage_bps: u16) -> Result<u16> {
+    if slippage_bps > MAX_SLIPPAGE_BPS {
+        return Err(Error::from(ActionError::InvalidSlippage));
+    }
+
+    Ok(slippage_bps)
+}
+
+fn validate_max_price_impact_pct(max_price_impact_pct: f64) -> Result<f64> {
+    if !max_price_impact_pct.is_finite() || max_price_impact_pct < 0.0 {
+        return Err(Error::from(ActionError::InvalidPriceImpactLimit));
+    }
+
+    Ok(max_price_impact_pct)
+}
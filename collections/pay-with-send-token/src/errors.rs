@@ -8,10 +8,22 @@ pub enum ActionError {
     InvalidReceiverPublicKey,
     #[error(msg = "Invalid token mint public key")]
     InvalidTokenMintPublicKey,
+    #[error(msg = "Invalid output mint public key")]
+    InvalidOutputMintPublicKey,
+    #[error(msg = "Invalid reference public key")]
+    InvalidReferencePublicKey,
+    #[error(msg = "Invalid memo, it must be between 1 and 128 bytes")]
+    InvalidMemo,
     #[error(msg = "Error obtaining token account data")]
     ErrorObtainingTokenAccountData,
+    #[error(msg = "Token mint is not owned by a supported token program")]
+    UnsupportedTokenProgram,
     #[error(msg = "Error obtaining token metadata")]
     ErrorObtainingTokenMetadata,
+    #[error(msg = "Token decimals are unavailable for this mint")]
+    TokenDecimalsUnavailable,
+    #[error(msg = "Token symbol is unavailable for this mint")]
+    TokenSymbolUnavailable,
     #[error(msg = "Internal server error")]
     InternalServerError,
     #[error(msg = "Unknown server error")]
@@ -20,5 +32,68 @@ pub enum ActionError {
     InvalidResponseBody,
     #[error(msg = "No quote was found for this token at this time")]
     QuoteNotFound,
-
+    #[error(msg = "No swap route was found for this token pair")]
+    NoRouteFound,
+    #[error(msg = "The amount is too small to be swapped")]
+    AmountTooSmall,
+    #[error(msg = "Rate limited by an upstream service, try again in a moment")]
+    RateLimited,
+    #[error(msg = "An upstream service is unavailable, try again in a moment")]
+    UpstreamUnavailable,
+    #[error(msg = "Invalid swap mode, use exact_in or exact_out")]
+    InvalidSwapMode,
+    #[error(msg = "Invalid slippage, use basis points between 0 and 10000")]
+    InvalidSlippage,
+    #[error(msg = "Invalid price impact limit")]
+    InvalidPriceImpactLimit,
+    #[error(msg = "The price impact of this swap exceeds the allowed limit")]
+    PriceImpactTooHigh,
+    #[error(msg = "The payment does not fit in a single transaction, even with the simplest swap route")]
+    TransactionTooLarge,
+    #[error(msg = "The payment does not fit in a single transaction, try fewer receivers or a shorter memo")]
+    PaymentTooLarge,
+    #[error(msg = "Invalid transaction signature")]
+    InvalidSignature,
+    #[error(msg = "Provide a transaction signature or a reference key")]
+    MissingPaymentIdentifier,
+    #[error(msg = "No payment was found for this signature or reference")]
+    PaymentNotFound,
+    #[error(msg = "Error obtaining the payment transaction")]
+    ErrorObtainingTransaction,
+    #[error(msg = "Invalid platform fee configuration")]
+    InvalidPlatformFee,
+    #[error(msg = "The platform fee account must hold SOL before it can receive this fee")]
+    PlatformFeeAccountUnfunded,
+    #[error(msg = "Invalid priority fee limit")]
+    InvalidPriorityFeeLimit,
+    #[error(msg = "An amount is required")]
+    MissingAmount,
+    #[error(msg = "Invoices are not enabled")]
+    InvoicesDisabled,
+    #[error(msg = "Invalid API key")]
+    InvalidApiKey,
+    #[error(msg = "Invalid invoice")]
+    InvalidInvoice,
+    #[error(msg = "This invoice has expired")]
+    InvoiceExpired,
+    #[error(msg = "Invalid splits, use up to 4 pubkey:bps pairs adding up to at most 10000")]
+    InvalidSplits,
+    #[error(msg = "Splitting a swapped payment needs exact_out mode")]
+    SplitsRequireExactOut,
+    #[error(msg = "Invoices can't be combined with splits")]
+    InvoiceSplitsUnsupported,
+    #[error(msg = "Tokens with a transfer fee can't be split between receivers")]
+    SplitsUnsupportedForTransferFee,
+    #[error(msg = "Payments to this receiver are not allowed")]
+    ReceiverDenied,
+    #[error(msg = "This token is not allowed for payments")]
+    TokenNotAllowed,
+    #[error(msg = "Error loading the payment lists")]
+    ErrorLoadingPaymentLists,
+    #[error(msg = "Invalid domain, use a .sol domain such as alice.sol")]
+    InvalidDomain,
+    #[error(msg = "This .sol domain does not exist")]
+    DomainNotFound,
+    #[error(msg = "This .sol domain is held by a program, such as a tokenized domain, and can't be paid")]
+    DomainOwnerNotAWallet,
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+use common::programs::JUPITER_PROGRAM_ID;
+use serde_json::json;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use std::sync::Mutex;
+use znap::prelude::*;
+
+use crate::swap_provider::{
+    Quote, QuoteError, QuoteRequest, RouteLimits, SwapInstructions, SwapProvider,
+};
+
+// Swaps one for one offline, the nth quote routes through the nth number of accounts, or finds
+// no route once they run out
+pub struct FakeSwapProvider {
+    route_accounts: Vec<usize>,
+    pub quoted_route_limits: Mutex<Vec<RouteLimits>>,
+}
+
+impl FakeSwapProvider {
+    pub fn new(route_accounts: &[usize]) -> FakeSwapProvider {
+        FakeSwapProvider {
+            route_accounts: route_accounts.to_vec(),
+            quoted_route_limits: Mutex::new(vec![]),
+        }
+    }
+}
+
+#[async_trait]
+impl SwapProvider for FakeSwapProvider {
+    async fn quote(&self, request: &QuoteRequest) -> std::result::Result<Quote, QuoteError> {
+        let mut quoted_route_limits = self.quoted_route_limits.lock().unwrap();
+        quoted_route_limits.push(request.route_limits);
+
+        let route_accounts = self
+            .route_accounts
+            .get(quoted_route_limits.len() - 1)
+            .ok_or(QuoteError::NoRoute)?;
+
+        Ok(Quote {
+            out_amount: request.amount,
+            other_amount_threshold: request.amount,
+            price_impact_pct: 0.0,
+            raw: json!({ "routeAccounts": route_accounts }),
+        })
+    }
+
+    async fn swap_instructions(
+        &self,
+        account_pubkey: &Pubkey,
+        destination_token_account: &Pubkey,
+        fee_token_account: Option<Pubkey>,
+        quote: Quote,
+    ) -> Result<SwapInstructions> {
+        let route_accounts = quote.raw["routeAccounts"].as_u64().unwrap_or_default();
+
+        let mut accounts = vec![
+            AccountMeta::new(*account_pubkey, true),
+            AccountMeta::new(*destination_token_account, false),
+        ];
+        accounts.extend(fee_token_account.map(|fee_account| AccountMeta::new(fee_account, false)));
+        accounts.extend((0..route_accounts).map(|_| AccountMeta::new(Pubkey::new_unique(), false)));
+
+        Ok(SwapInstructions {
+            token_ledger_instruction: None,
+            compute_budget_instructions: vec![ComputeBudgetInstruction::set_compute_unit_limit(
+                1_400_000,
+            )],
+            setup_instructions: vec![],
+            swap_instruction: Instruction::new_with_bytes(JUPITER_PROGRAM_ID, &[], accounts),
+            cleanup_instruction: None,
+            address_lookup_table_addresses: vec![],
+            prioritization_fee_lamports: 0,
+        })
+    }
+}
@@ -1,12 +1,10 @@
-use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use znap::prelude::*;
 
 use crate::errors::ActionError;
+use crate::http::{http_client, send_with_retry};
 
-pub async fn get_token_metadata(mint_address: &String, rpc: &String) -> Result<Value> {
-    let client = Client::new();
+pub async fn get_token_metadata(mint_address: &str, rpc: &str) -> Result<HeliusAsset> {
     let base_url = rpc;
 
     let req = HeliusRequest {
@@ -18,22 +16,49 @@ pub async fn get_token_metadata(mint_address: &String, rpc: &String) -> Result<V
         },
     };
 
-    let response = client
-        .post(format!("{}", base_url))
-        .header("Accept", "application/json")
-        .json(&req)
-        .send()
+    let response = send_with_retry(
+        http_client()?
+            .post(format!("{}", base_url))
+            .header("Accept", "application/json")
+            .json(&req),
+    )
+    .await
+    .or_else(|error| Err(Error::from(error)))?;
+
+    let body = response
+        .json::<HeliusResponse>()
         .await
-        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+        .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)))?;
+
+    // Plain RPCs and unknown mints answer with an error payload instead of a result
+    body.result
+        .ok_or(Error::from(ActionError::ErrorObtainingTokenMetadata))
+}
+
+#[derive(Debug, Deserialize)]
+struct HeliusResponse {
+    result: Option<HeliusAsset>,
+}
 
-    if response.status() == StatusCode::OK {
-        return response
-            .json::<Value>()
-            .await
-            .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)));
-    }
+#[derive(Debug, Deserialize)]
+pub struct HeliusAsset {
+    pub content: Option<HeliusAssetContent>,
+    pub token_info: Option<HeliusTokenInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeliusAssetContent {
+    pub metadata: Option<HeliusAssetMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeliusAssetMetadata {
+    pub symbol: Option<String>,
+}
 
-    return Err(Error::from(ActionError::UnknownServerError));
+#[derive(Debug, Deserialize)]
+pub struct HeliusTokenInfo {
+    pub symbol: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,121 @@
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{sync::OnceLock, time::Duration};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: u32 = 3;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+// One pooled client shared by every upstream API, never one without timeouts
+pub fn http_client() -> Result<&'static Client> {
+    if let Some(client) = HTTP_CLIENT.get() {
+        return Ok(client);
+    }
+
+    let client = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .or_else(|err| {
+            println!("Failed to build the HTTP client: {}", err);
+            Err(Error::from(ActionError::InternalServerError))
+        })?;
+
+    Ok(HTTP_CLIENT.get_or_init(|| client))
+}
+
+#[derive(Debug)]
+pub enum UpstreamError {
+    // The request never got a response, e.g. a timeout or a refused connection
+    Unreachable,
+    Status {
+        status: StatusCode,
+        error_code: Option<String>,
+        message: Option<String>,
+    },
+}
+
+impl UpstreamError {
+    async fn from_response(response: Response) -> UpstreamError {
+        let status = response.status();
+        let body = response.json::<UpstreamErrorBody>().await.ok();
+
+        let error_code = body.as_ref().and_then(|body| body.error_code.clone());
+        // REST APIs answer with a plain message, JSON-RPC ones with an object
+        let message = body.and_then(|body| match body.error {
+            Some(Value::String(message)) => Some(message),
+            Some(error) => error["message"].as_str().map(|message| message.to_string()),
+            None => None,
+        });
+
+        UpstreamError::Status {
+            status,
+            error_code,
+            message,
+        }
+    }
+}
+
+impl From<UpstreamError> for Error {
+    fn from(error: UpstreamError) -> Error {
+        println!("Upstream error: {:?}", error);
+
+        match error {
+            UpstreamError::Unreachable => Error::from(ActionError::UpstreamUnavailable),
+            UpstreamError::Status { status, .. } if status == StatusCode::TOO_MANY_REQUESTS => {
+                Error::from(ActionError::RateLimited)
+            }
+            UpstreamError::Status { status, .. } if status.is_server_error() => {
+                Error::from(ActionError::UpstreamUnavailable)
+            }
+            UpstreamError::Status { .. } => Error::from(ActionError::UnknownServerError),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpstreamErrorBody {
+    error: Option<Value>,
+    error_code: Option<String>,
+}
+
+// Sends the request, retrying with exponential backoff on rate limits, server errors and timeouts
+pub async fn send_with_retry(
+    request: RequestBuilder,
+) -> std::result::Result<Response, UpstreamError> {
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let last_attempt = attempt == MAX_ATTEMPTS;
+        let attempt_request = request.try_clone().ok_or(UpstreamError::Unreachable)?;
+
+        match attempt_request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) if is_retryable(response.status()) && !last_attempt => {
+                println!("Retrying upstream request after status {}", response.status());
+            }
+            Ok(response) => return Err(UpstreamError::from_response(response).await),
+            Err(error) if !last_attempt => {
+                println!("Retrying upstream request after error: {}", error);
+            }
+            Err(_) => return Err(UpstreamError::Unreachable),
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+
+    Err(UpstreamError::Unreachable)
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
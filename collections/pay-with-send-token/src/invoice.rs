@@ -0,0 +1,252 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+pub const DEFAULT_INVOICE_TTL_SECS: u64 = 15 * 60;
+// How long a signed invoice request can be replayed, with some leeway for clock skew
+const MAX_REQUEST_AGE_SECS: u64 = 5 * 60;
+const MAX_REQUEST_CLOCK_SKEW_SECS: u64 = 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// A payment request whose terms are fixed by the merchant, signed with INVOICE_SECRET
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invoice {
+    pub receiver: String,
+    pub output_mint: String,
+    // In base units of the output mint, always paid in ExactOut mode
+    pub amount: u64,
+    pub memo: Option<String>,
+    // Unix timestamp in seconds
+    pub expires_at: u64,
+}
+
+impl Invoice {
+    pub fn new(
+        receiver: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        memo: Option<String>,
+        ttl_secs: u64,
+    ) -> Invoice {
+        Invoice {
+            receiver: receiver.to_string(),
+            output_mint: output_mint.to_string(),
+            amount,
+            memo,
+            expires_at: now().saturating_add(ttl_secs),
+        }
+    }
+
+    // Encodes the invoice as `payload.signature`, both base64url so it fits in a query param
+    pub fn sign(&self, secret: &str) -> Result<String> {
+        let payload = serde_json::to_vec(self)
+            .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+
+        let mut mac = hmac(secret)?;
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        Ok(format!("{}.{}", payload, signature))
+    }
+
+    // Checks the signature and that the invoice is for this receiver, expiry is left to the caller
+    pub fn verify(token: &str, secret: &str, receiver: &Pubkey) -> Result<Invoice> {
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or(Error::from(ActionError::InvalidInvoice))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .or_else(|_| Err(Error::from(ActionError::InvalidInvoice)))?;
+
+        let mut mac = hmac(secret)?;
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature)
+            .or_else(|_| Err(Error::from(ActionError::InvalidInvoice)))?;
+
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .or_else(|_| Err(Error::from(ActionError::InvalidInvoice)))?;
+        let invoice = serde_json::from_slice::<Invoice>(&payload)
+            .or_else(|_| Err(Error::from(ActionError::InvalidInvoice)))?;
+
+        if invoice.receiver != receiver.to_string() {
+            return Err(Error::from(ActionError::InvalidInvoice));
+        }
+
+        Ok(invoice)
+    }
+
+    pub fn output_mint(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.output_mint).or_else(|_| Err(Error::from(ActionError::InvalidInvoice)))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now() >= self.expires_at
+    }
+
+    pub fn check_expiry(&self) -> Result<()> {
+        if self.is_expired() {
+            return Err(Error::from(ActionError::InvoiceExpired));
+        }
+
+        Ok(())
+    }
+}
+
+// Merchants never send INVOICE_API_KEY, they sign "{timestamp}.{request}" with it using
+// HMAC-SHA256. The signature is compared in constant time, and only recent requests are accepted
+pub fn verify_request(
+    api_key: &str,
+    request: &str,
+    timestamp: u64,
+    signature: &str,
+) -> Result<()> {
+    let now = now();
+
+    if timestamp.saturating_add(MAX_REQUEST_AGE_SECS) < now
+        || timestamp > now.saturating_add(MAX_REQUEST_CLOCK_SKEW_SECS)
+    {
+        return Err(Error::from(ActionError::InvalidApiKey));
+    }
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .or_else(|_| Err(Error::from(ActionError::InvalidApiKey)))?;
+
+    let mut mac = hmac(api_key)?;
+    mac.update(format!("{}.{}", timestamp, request).as_bytes());
+    mac.verify_slice(&signature)
+        .or_else(|_| Err(Error::from(ActionError::InvalidApiKey)))
+}
+
+fn hmac(secret: &str) -> Result<HmacSha256> {
+    HmacSha256::new_from_slice(secret.as_bytes())
+        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "invoice-secret";
+    const API_KEY: &str = "merchant-api-key";
+    const REQUEST: &str = "SEND/alice.sol?amount=12.5&output_mint=&memo=order-42&expires_in=";
+
+    fn receiver() -> Pubkey {
+        Pubkey::new_from_array([7; 32])
+    }
+
+    fn invoice() -> Invoice {
+        Invoice::new(&receiver(), &Pubkey::new_from_array([9; 32]), 12_500_000, None, 60)
+    }
+
+    // What a merchant does on its side to sign an invoice request
+    fn sign_request(api_key: &str, request: &str, timestamp: u64) -> Result<String> {
+        let mut mac = hmac(api_key)?;
+        mac.update(format!("{}.{}", timestamp, request).as_bytes());
+
+        Ok(URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn verifies_a_signed_invoice() {
+        let token = invoice().sign(SECRET).ok().unwrap();
+        let verified = Invoice::verify(&token, SECRET, &receiver()).ok().unwrap();
+
+        assert_eq!(verified.amount, 12_500_000);
+        assert_eq!(verified.output_mint, Pubkey::new_from_array([9; 32]).to_string());
+        assert!(!verified.is_expired());
+    }
+
+    #[test]
+    fn rejects_another_secret() {
+        let token = invoice().sign(SECRET).ok().unwrap();
+
+        assert!(Invoice::verify(&token, "another-secret", &receiver()).is_err());
+    }
+
+    #[test]
+    fn rejects_another_receiver() {
+        let token = invoice().sign(SECRET).ok().unwrap();
+
+        assert!(Invoice::verify(&token, SECRET, &Pubkey::new_from_array([8; 32])).is_err());
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let token = invoice().sign(SECRET).ok().unwrap();
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let mut tampered = invoice();
+        tampered.amount = 1;
+        let tampered_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&tampered).unwrap());
+
+        let tampered_token = format!("{}.{}", tampered_payload, signature);
+
+        assert!(Invoice::verify(&tampered_token, SECRET, &receiver()).is_err());
+        assert!(Invoice::verify("not-a-token", SECRET, &receiver()).is_err());
+    }
+
+    #[test]
+    fn expires() {
+        let mut invoice = invoice();
+        invoice.expires_at = now() - 1;
+
+        assert!(invoice.is_expired());
+        assert!(invoice.check_expiry().is_err());
+    }
+
+    #[test]
+    fn verifies_a_signed_request() {
+        let timestamp = now();
+        let signature = sign_request(API_KEY, REQUEST, timestamp).ok().unwrap();
+
+        assert!(verify_request(API_KEY, REQUEST, timestamp, &signature).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_request_signed_with_another_key_or_for_other_terms() {
+        let timestamp = now();
+        let signature = sign_request("another-key", REQUEST, timestamp).ok().unwrap();
+
+        assert!(verify_request(API_KEY, REQUEST, timestamp, &signature).is_err());
+
+        let signature = sign_request(API_KEY, REQUEST, timestamp).ok().unwrap();
+        let other_request = REQUEST.replace("12.5", "0.01");
+
+        assert!(verify_request(API_KEY, &other_request, timestamp, &signature).is_err());
+        assert!(verify_request(API_KEY, REQUEST, timestamp + 1, &signature).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_and_future_requests() {
+        let stale = now() - MAX_REQUEST_AGE_SECS - 1;
+        let signature = sign_request(API_KEY, REQUEST, stale).ok().unwrap();
+
+        assert!(verify_request(API_KEY, REQUEST, stale, &signature).is_err());
+
+        let future = now() + MAX_REQUEST_CLOCK_SKEW_SECS + 10;
+        let signature = sign_request(API_KEY, REQUEST, future).ok().unwrap();
+
+        assert!(verify_request(API_KEY, REQUEST, future, &signature).is_err());
+    }
+}
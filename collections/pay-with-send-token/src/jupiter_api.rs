@@ -1,125 +1,235 @@
-use reqwest::{Client, StatusCode};
+use async_trait::async_trait;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
 use znap::prelude::*;
 
 use crate::errors::ActionError;
-use crate::field_instruction;
-use crate::field_pubkey;
-
-pub async fn get_swap_instructions(
-    account_pubkey: &String,
-    destination_token_account: &String,
-    input_mint_address: &String,
-    output_mint_address: &String,
-    amount: u64,
-) -> Result<SwapInstructions> {
-    let client = Client::new();
-    let base_url = "https://quote-api.jup.ag/v6";
-
-    let max_accounts = "18";
-
-    let quote_response = client
-        .get(format!(
-            "{}/quote?inputMint={}&outputMint={}&amount={}&maxAccounts={}",
-            base_url, input_mint_address, output_mint_address, amount, max_accounts
-        ))
-        .send()
-        .await
-        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?
-        .json::<QuoteResponse>()
-        .await
-        .or_else(|_| Err(Error::from(ActionError::QuoteNotFound)))?;
+use crate::http::{http_client, send_with_retry, UpstreamError};
+use crate::swap_provider::{
+    Quote, QuoteError, QuoteRequest, SwapInstructions, SwapMode, SwapProvider,
+};
 
-    let swap_request = SwapRequest {
-        quote_response,
-        user_public_key: account_pubkey.to_string(),
-        destination_token_account: destination_token_account.to_string(),
-    };
+pub const DEFAULT_JUPITER_API_URL: &str = "https://quote-api.jup.ag/v6";
+const NO_ROUTE_ERROR_CODES: [&str; 3] = [
+    "COULD_NOT_FIND_ANY_ROUTE",
+    "NO_ROUTES_FOUND",
+    "TOKEN_NOT_TRADABLE",
+];
 
-    let swap_instructions = client
-        .post(format!("{}/swap-instructions", base_url))
-        .header("Accept", "application/json")
-        .json(&swap_request)
-        .send()
-        .await
-        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+pub struct JupiterSwapProvider {
+    base_url: String,
+}
+
+impl JupiterSwapProvider {
+    pub fn new(base_url: &str) -> JupiterSwapProvider {
+        JupiterSwapProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl SwapProvider for JupiterSwapProvider {
+    async fn quote(&self, request: &QuoteRequest) -> std::result::Result<Quote, QuoteError> {
+        let mut url = format!(
+            "{}/quote?inputMint={}&outputMint={}&amount={}&swapMode={}&slippageBps={}&maxAccounts={}",
+            self.base_url,
+            request.input_mint,
+            request.output_mint,
+            request.amount,
+            swap_mode_param(request.swap_mode),
+            request.slippage_bps,
+            request.route_limits.max_accounts
+        );
+
+        if request.route_limits.only_direct_routes {
+            url.push_str("&onlyDirectRoutes=true");
+        }
 
-    if swap_instructions.status() == StatusCode::OK {
-        return swap_instructions
-            .json::<SwapInstructions>()
+        if let Some(platform_fee_bps) = request.platform_fee_bps {
+            url.push_str(&format!("&platformFeeBps={}", platform_fee_bps));
+        }
+
+        let http_client = http_client().map_err(QuoteError::Other)?;
+
+        let raw_quote = send_with_retry(http_client.get(url))
+            .await
+            .or_else(|error| Err(jupiter_quote_error(error)))?
+            .json::<Value>()
             .await
-            .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)));
+            .or(Err(QuoteError::Other(Error::from(ActionError::InvalidResponseBody))))?;
+
+        parse_quote(raw_quote).map_err(QuoteError::Other)
     }
 
-    return Err(Error::from(ActionError::UnknownServerError));
+    async fn swap_instructions(
+        &self,
+        account_pubkey: &Pubkey,
+        destination_token_account: &Pubkey,
+        fee_token_account: Option<Pubkey>,
+        quote: Quote,
+    ) -> Result<SwapInstructions> {
+        let swap_request = SwapRequest {
+            quote_response: quote.raw,
+            user_public_key: account_pubkey.to_string(),
+            destination_token_account: destination_token_account.to_string(),
+            // Wrap native SOL input in setup and close the temporary wSOL account in cleanup
+            wrap_and_unwrap_sol: true,
+            fee_account: fee_token_account.map(|fee_token_account| fee_token_account.to_string()),
+        };
+
+        send_with_retry(
+            http_client()?
+                .post(format!("{}/swap-instructions", self.base_url))
+                .header("Accept", "application/json")
+                .json(&swap_request),
+        )
+        .await
+        .or_else(|error| Err(jupiter_error(error)))?
+        .json::<SwapInstructions>()
+        .await
+        .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct SplTokenInfo {
-    decimals: u8,
-    supply: u64,
+fn jupiter_quote_error(error: UpstreamError) -> QuoteError {
+    match &error {
+        UpstreamError::Status {
+            error_code: Some(error_code),
+            ..
+        } if NO_ROUTE_ERROR_CODES.contains(&error_code.as_str()) => {
+            println!("Jupiter error: {:?}", error);
+            QuoteError::NoRoute
+        }
+        _ => QuoteError::Other(jupiter_error(error)),
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct SwapInfo {
-    amm_key: String,
-    label: String,
-    input_mint: String,
-    output_mint: String,
-    in_amount: String,
-    out_amount: String,
-    fee_amount: String,
-    fee_mint: String,
+// Turns Jupiter's error codes into errors the blink can show, e.g. "no route" vs "amount too small"
+fn jupiter_error(error: UpstreamError) -> Error {
+    let action_error = match &error {
+        UpstreamError::Status {
+            error_code: Some(error_code),
+            ..
+        } if NO_ROUTE_ERROR_CODES.contains(&error_code.as_str()) => Some(ActionError::NoRouteFound),
+        UpstreamError::Status {
+            message: Some(message),
+            ..
+        } if message.to_lowercase().contains("too small") => Some(ActionError::AmountTooSmall),
+        UpstreamError::Status { status, .. }
+            if status.is_client_error() && *status != StatusCode::TOO_MANY_REQUESTS =>
+        {
+            Some(ActionError::QuoteNotFound)
+        }
+        _ => None,
+    };
+
+    match action_error {
+        Some(action_error) => {
+            println!("Jupiter error: {:?}", error);
+            Error::from(action_error)
+        }
+        None => Error::from(error),
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Route {
-    swap_info: SwapInfo,
-    percent: u32,
+// The quote goes back to /swap-instructions as received, with the fields we don't model
+fn parse_quote(raw_quote: Value) -> Result<Quote> {
+    serde_json::from_value::<QuoteResponse>(raw_quote.clone())
+        .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)))?
+        .into_quote(raw_quote)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn swap_mode_param(swap_mode: SwapMode) -> &'static str {
+    match swap_mode {
+        SwapMode::ExactIn => "ExactIn",
+        SwapMode::ExactOut => "ExactOut",
+    }
+}
+
+// Only what the payment reads, the rest of the quote stays in Quote.raw
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct QuoteResponse {
-    input_mint: String,
-    in_amount: String,
-    output_mint: String,
     out_amount: String,
     other_amount_threshold: String,
-    swap_mode: String,
-    slippage_bps: u32,
-    platform_fee: Option<u32>,
+    // A fraction, 0.01 means a 1% price impact
     price_impact_pct: String,
-    route_plan: Vec<Route>,
-    context_slot: u64,
-    time_taken: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl QuoteResponse {
+    fn into_quote(self, raw: Value) -> Result<Quote> {
+        let parse_amount = |amount: &String| {
+            amount
+                .parse::<u64>()
+                .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)))
+        };
+
+        let price_impact = self
+            .price_impact_pct
+            .parse::<f64>()
+            .or_else(|_| Err(Error::from(ActionError::InvalidResponseBody)))?;
+
+        Ok(Quote {
+            out_amount: parse_amount(&self.out_amount)?,
+            other_amount_threshold: parse_amount(&self.other_amount_threshold)?,
+            price_impact_pct: price_impact * 100.0,
+            raw,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct SwapRequest {
-    quote_response: QuoteResponse,
+    quote_response: Value,
     user_public_key: String,
     destination_token_account: String,
+    wrap_and_unwrap_sol: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee_account: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct SwapInstructions {
-    #[serde(with = "field_instruction::option_instruction")]
-    pub token_ledger_instruction: Option<Instruction>,
-    #[serde(with = "field_instruction::vec_instruction")]
-    pub compute_budget_instructions: Vec<Instruction>,
-    #[serde(with = "field_instruction::vec_instruction")]
-    pub setup_instructions: Vec<Instruction>,
-    #[serde(with = "field_instruction::instruction")]
-    pub swap_instruction: Instruction,
-    #[serde(with = "field_instruction::option_instruction")]
-    pub cleanup_instruction: Option<Instruction>,
-    #[serde(with = "field_pubkey::vec")]
-    pub address_lookup_table_addresses: Vec<Pubkey>,
-    pub prioritization_fee_lamports: u64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_the_quote_as_received() {
+        let raw_quote = json!({
+            "inputMint": "So11111111111111111111111111111111111111112",
+            "outputMint": "SENDdRQtYMWaQrBroBrJ2Q53fgVuq95CV9UPGEvpCxa",
+            "inAmount": "1000000",
+            "outAmount": "2500000",
+            "otherAmountThreshold": "2487500",
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "priceImpactPct": "0.0123",
+            "routePlan": [],
+            "contextSlot": 290000000,
+            "timeTaken": 0.01,
+            "mostReliableAmmsQuoteReport": { "info": {} }
+        });
+
+        let quote = parse_quote(raw_quote.clone()).ok().unwrap();
+
+        assert_eq!(quote.out_amount, 2_500_000);
+        assert_eq!(quote.other_amount_threshold, 2_487_500);
+        assert!((quote.price_impact_pct - 1.23).abs() < 1e-9);
+        assert_eq!(quote.raw, raw_quote);
+    }
+
+    #[test]
+    fn rejects_a_malformed_quote() {
+        assert!(parse_quote(json!({ "outAmount": "2500000" })).is_err());
+        assert!(parse_quote(json!({
+            "outAmount": "a lot",
+            "otherAmountThreshold": "2487500",
+            "priceImpactPct": "0"
+        }))
+        .is_err());
+    }
 }
@@ -1,22 +1,46 @@
+use common::amount::DecimalAmount;
+use config::PayConfig;
 use errors::ActionError;
-use helius_api::get_token_metadata;
-use jupiter_api::get_swap_instructions;
-use solana_sdk::{message::Message, pubkey, pubkey::Pubkey, transaction::Transaction};
-use spl_associated_token_account::get_associated_token_address;
-use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
-use spl_token::ID as TOKEN_PROGRAM_ID;
-use utils::format_pubkey;
+use invoice::{Invoice, DEFAULT_INVOICE_TTL_SECS};
+use lists::payment_lists;
+use metadata::get_token_symbol;
+use payment::{build_payment, finalize_payment, Payment};
+use quote_preview::{get_quote_previews, QuotePreview, PRESET_AMOUNTS};
+use reference::{memo_instruction, parse_reference};
+use sns::{is_sol_domain, resolve_receiver};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use splits::{check_split_mint, check_split_mode, parse_splits};
+use swap_provider::SwapMode;
+use token::{get_mint_account, parse_token_mint};
+use url::form_urlencoded::byte_serialize;
+use utils::{format_amount, format_pubkey};
+use verify::{find_reference_signature, parse_signature, verify_payment, ExpectedPayment};
 use std::str::FromStr;
 use znap::prelude::*;
 
+mod config;
 mod errors;
+#[cfg(test)]
+mod fake_swap_provider;
 mod field_instruction;
 mod field_pubkey;
 mod helius_api;
+mod http;
+mod invoice;
 mod jupiter_api;
+mod lists;
+mod metadata;
+mod payment;
+mod platform_fee;
+mod quote_preview;
+mod reference;
+mod sns;
+mod splits;
+mod swap_provider;
+mod token;
 mod utils;
-
-const SEND_MINT_ADDRESS: Pubkey = pubkey!("SENDdRQtYMWaQrBroBrJ2Q53fgVuq95CV9UPGEvpCxa");
+mod verify;
 
 #[collection]
 pub mod pay_with_send_token { 
@@ -25,108 +49,512 @@ pub mod pay_with_send_token {
     fn pay(ctx: Context<PayAction>) -> Result<ActionTransaction> {
         let account_pubkey = Pubkey::from_str(&ctx.payload.account)
             .or_else(|_| Err(Error::from(ActionError::InvalidAccountPublicKey)))?;
-        let receiver_pubkey = Pubkey::from_str(&ctx.params.receiver)
-            .or_else(|_| Err(Error::from(ActionError::InvalidReceiverPublicKey)))?;
-        let token_mint = Pubkey::from_str(&ctx.params.token_mint)
-            .or_else(|_| Err(Error::from(ActionError::InvalidTokenMintPublicKey)))?;
+        let client = RpcClient::new(ctx.env.rpc_url.clone());
+        let receiver_pubkey = resolve_receiver(&client, &ctx.params.receiver).await?;
+        let token_mint = parse_token_mint(&ctx.params.token_mint)?;
+        let config = PayConfig::from_env()?;
+        let invoice = config.invoice(&ctx.query.invoice, &receiver_pubkey)?;
+        let slippage_bps = config.slippage_bps(ctx.query.slippage_bps)?;
+        let max_price_impact_pct = config.max_price_impact_pct(ctx.query.max_price_impact_pct)?;
+        let reference = parse_reference(&ctx.query.reference)?;
 
-        let res = get_token_metadata(&ctx.params.token_mint, &ctx.env.rpc_url)
-            .await
-            .or_else(|_| Err(Error::from(ActionError::ErrorObtainingTokenMetadata)))?;
+        // An invoice fixes the receiver, output mint, amount and memo, whatever the query says
+        let (output_mint, swap_mode, memo, splits) = match &invoice {
+            Some(invoice) => {
+                invoice.check_expiry()?;
 
-        let token_decimals = res["result"]["token_info"]["decimals"].as_u64().unwrap();
+                // The invoice signature doesn't cover splits, so they can't ride along
+                if ctx.query.splits.is_some() {
+                    return Err(Error::from(ActionError::InvoiceSplitsUnsupported));
+                }
 
-        let decimals_result = 10u32.pow(token_decimals as u32);
-        let amount = (ctx.query.amount * (decimals_result as f32)) as u64;
+                (
+                    invoice.output_mint()?,
+                    SwapMode::ExactOut,
+                    invoice.memo.clone(),
+                    parse_splits(&receiver_pubkey, &None)?,
+                )
+            }
+            None => (
+                config.output_mint(&ctx.query.output_mint)?,
+                SwapMode::from_query(&ctx.query.mode)?,
+                ctx.query.memo.clone(),
+                parse_splits(&receiver_pubkey, &ctx.query.splits)?,
+            ),
+        };
 
-        let receiver_send_ata_address = get_associated_token_address(&receiver_pubkey, &SEND_MINT_ADDRESS);
+        let receivers: Vec<Pubkey> = splits.iter().map(|split| split.receiver).collect();
+        payment_lists()?.check(&receivers, &[token_mint, output_mint])?;
 
-        let create_send_ata_instruction = create_associated_token_account_idempotent(
-            &account_pubkey,
-            &receiver_pubkey,
-            &SEND_MINT_ADDRESS,
-            &TOKEN_PROGRAM_ID,
-        );
+        check_split_mode(&splits, swap_mode, token_mint != output_mint)?;
+
+        let token_mint_account = get_mint_account(&client, &token_mint).await?;
+        let output_mint_account = get_mint_account(&client, &output_mint).await?;
+
+        check_split_mint(&splits, &output_mint_account)?;
 
-        let swap_instructions = get_swap_instructions(
-            &account_pubkey.to_string(),
-            &receiver_send_ata_address.to_string(),
-            &token_mint.to_string(),
-            &SEND_MINT_ADDRESS.to_string(),
+        let token_decimals = token_mint_account.decimals;
+
+        // In ExactOut mode the amount is denominated in the receiving token
+        let amount_decimals = match swap_mode {
+            SwapMode::ExactIn => token_decimals,
+            SwapMode::ExactOut => output_mint_account.decimals,
+        };
+
+        let amount = match &invoice {
+            Some(invoice) => invoice.amount,
+            None => {
+                let amount = ctx
+                    .query
+                    .amount
+                    .as_ref()
+                    .ok_or(Error::from(ActionError::MissingAmount))?;
+
+                DecimalAmount::parse(amount)?.to_base_units(amount_decimals)?
+            }
+        };
+
+        // The receiver must net the exact amount, so cover the output mint's transfer fee
+        let amount = match swap_mode {
+            SwapMode::ExactIn => amount,
+            SwapMode::ExactOut => output_mint_account.amount_before_fee(amount),
+        };
+
+        let payment = Payment {
+            payer: account_pubkey,
+            token_mint,
+            token_mint_account,
+            output_mint,
+            output_mint_account,
+            swap_mode,
             amount,
+            splits,
+            reference,
+            memo,
+            slippage_bps,
+            max_price_impact_pct,
+            platform_fee: config.platform_fee,
+        };
+
+        let payment_instructions =
+            build_payment(&client, config.swap_provider.as_ref(), &payment).await?;
+
+        let finalized_transaction = finalize_payment(
+            &payment_instructions.instructions,
+            &account_pubkey,
+            &ctx.env.rpc_url,
+            config.max_priority_fee_lamports,
         )
-        .await
-        .or_else(|_| Err(Error::from(ActionError::QuoteNotFound)))?;
+        .await?;
 
-        let token_ledger_instruction = swap_instructions.token_ledger_instruction;
-        let swap_compute_budget_instructions = swap_instructions.compute_budget_instructions;
-        let setup_instructions = swap_instructions.setup_instructions;
-        let swap_instruction = swap_instructions.swap_instruction;
-        let cleanup_instruction = swap_instructions.cleanup_instruction;
+        let message = match swap_mode {
+            SwapMode::ExactIn => format!("Payment successfully sent with reference {}", reference),
+            SwapMode::ExactOut => {
+                let token_symbol =
+                    get_token_symbol(&client, &ctx.env.rpc_url, &token_mint).await;
 
-        let mut instructions = vec![create_send_ata_instruction];
+                format!(
+                    "Payment successfully sent with reference {}, spending at most {} {}",
+                    reference,
+                    format_amount(payment_instructions.max_input_amount, token_decimals),
+                    token_symbol
+                )
+            }
+        };
 
-        if let Some(instruction) = token_ledger_instruction {
-            instructions.push(instruction);
+        Ok(finalized_transaction.into_action_transaction(&message))
+    }
+
+    fn get_pay(ctx: Context<PayAction>) -> Result<ActionMetadata> {
+        let token_mint = &ctx.params.token_mint;
+        let receiver_address = &ctx.params.receiver;
+        let client = RpcClient::new(ctx.env.rpc_url.clone());
+        let receiver_pubkey = resolve_receiver(&client, receiver_address).await?;
+        let config = PayConfig::from_env()?;
+        let invoice = config.invoice(&ctx.query.invoice, &receiver_pubkey)?;
+        let slippage_bps = config.slippage_bps(ctx.query.slippage_bps)?;
+        let max_price_impact_pct = config.max_price_impact_pct(ctx.query.max_price_impact_pct)?;
+
+        let (output_mint, swap_mode, splits) = match &invoice {
+            Some(_) if ctx.query.splits.is_some() => {
+                return Err(Error::from(ActionError::InvoiceSplitsUnsupported))
+            }
+            Some(invoice) => (
+                invoice.output_mint()?,
+                SwapMode::ExactOut,
+                parse_splits(&receiver_pubkey, &None)?,
+            ),
+            None => (
+                config.output_mint(&ctx.query.output_mint)?,
+                SwapMode::from_query(&ctx.query.mode)?,
+                parse_splits(&receiver_pubkey, &ctx.query.splits)?,
+            ),
+        };
+        let token_mint_pubkey = parse_token_mint(token_mint)?;
+        check_split_mode(&splits, swap_mode, token_mint_pubkey != output_mint)?;
+
+        if splits.len() > 1 {
+            check_split_mint(&splits, &get_mint_account(&client, &output_mint).await?)?;
+        }
+
+        // A rejected receiver or token still unfurls, but can't be paid
+        let receivers: Vec<Pubkey> = splits.iter().map(|split| split.receiver).collect();
+
+        if let Some(rejection) =
+            payment_lists()?.rejection(&receivers, &[token_mint_pubkey, output_mint])
+        {
+            return Ok(ActionMetadata {
+                title: "Pay using any Solana token".to_string(),
+                description: rejection.message().to_string(),
+                icon: "https://raw.githubusercontent.com/leandrogavidia/files/main/payments-with-send-token.png".to_string(),
+                label: "Send payment!".to_string(),
+                disabled: true,
+                error: Some(znap::ActionError {
+                    message: rejection.message().to_string(),
+                }),
+                links: None,
+            });
+        }
+
+        let token_symbol = get_token_symbol(&client, &ctx.env.rpc_url, &token_mint_pubkey).await;
+        let output_symbol = get_token_symbol(&client, &ctx.env.rpc_url, &output_mint).await;
+
+        // A domain is easier to recognize than a truncated key
+        let receiver_label = if is_sol_domain(receiver_address) {
+            receiver_address.to_lowercase()
+        } else {
+            format_pubkey(&receiver_address.to_string(), 10)
+        };
+
+        let label = "Send payment!";
+        let mut description = match swap_mode {
+            SwapMode::ExactIn => format!(
+                "Pay in {} and {} receives in {}",
+                token_symbol, receiver_label, output_symbol
+            ),
+            SwapMode::ExactOut => format!(
+                "Pay in {} and {} receives the exact amount in {}",
+                token_symbol, receiver_label, output_symbol
+            ),
+        };
+
+        if let Some(platform_fee) = &config.platform_fee {
+            description.push_str(&format!(". A {}% platform fee applies", platform_fee.fee_pct()));
+        }
+
+        let amount_symbol = match swap_mode {
+            SwapMode::ExactIn => &token_symbol,
+            SwapMode::ExactOut => &output_symbol,
+        };
+
+        // Reference and memo set by the merchant are carried over to every action
+        let mut payment_params = String::new();
+
+        if ctx.query.reference.is_some() {
+            let reference = parse_reference(&ctx.query.reference)?;
+            payment_params.push_str(&format!("&reference={}", reference));
         }
 
-        instructions.extend_from_slice(&swap_compute_budget_instructions);
-        instructions.extend_from_slice(&setup_instructions);
-        instructions.push(swap_instruction);
+        // An invoice gets a single button for its fixed amount, without an amount input
+        if let (Some(invoice), Some(invoice_token)) = (&invoice, &ctx.query.invoice) {
+            let output_mint_account = get_mint_account(&client, &output_mint).await?;
+            let invoice_label = format!(
+                "Pay {} {}",
+                format_amount(invoice.amount, output_mint_account.decimals),
+                output_symbol
+            );
+
+            let (links, error) = if invoice.is_expired() {
+                let error = znap::ActionError {
+                    message: "This invoice has expired".to_string(),
+                };
+
+                (None, Some(error))
+            } else {
+                let action = LinkedAction {
+                    label: invoice_label.clone(),
+                    href: format!(
+                        "/api/pay/{}/{}?invoice={}&slippage_bps={}&max_price_impact_pct={}{}",
+                        token_mint,
+                        receiver_address,
+                        invoice_token,
+                        slippage_bps,
+                        max_price_impact_pct,
+                        payment_params
+                    ),
+                    parameters: vec![],
+                };
+
+                (Some(ActionLinks { actions: vec![action] }), None)
+            };
 
-        if let Some(instruction) = cleanup_instruction {
-            instructions.push(instruction);
+            return Ok(ActionMetadata {
+                title: format!("Pay with {} using any Solana token", output_symbol),
+                description,
+                icon: "https://raw.githubusercontent.com/leandrogavidia/files/main/payments-with-send-token.png".to_string(),
+                label: invoice_label,
+                disabled: error.is_some(),
+                error,
+                links,
+            });
         }
 
-        let message = Message::new(&instructions, None);
-        let transaction = Transaction::new_unsigned(message);
+        if let Some(memo) = &ctx.query.memo {
+            memo_instruction(memo)?;
+            let encoded_memo: String = byte_serialize(memo.as_bytes()).collect();
+            payment_params.push_str(&format!("&memo={}", encoded_memo));
+        }
+
+        if let Some(splits) = &ctx.query.splits {
+            description.push_str(&format!(
+                ". The payment is split between {} receivers",
+                splits.len()
+            ));
+            let encoded_splits: String = byte_serialize(splits.as_bytes()).collect();
+            payment_params.push_str(&format!("&splits={}", encoded_splits));
+        }
+
+        let href = |amount: &str| {
+            format!(
+                "/api/pay/{}/{}?amount={}&output_mint={}&mode={}&slippage_bps={}&max_price_impact_pct={}{}",
+                token_mint,
+                receiver_address,
+                amount,
+                output_mint,
+                swap_mode.as_query(),
+                slippage_bps,
+                max_price_impact_pct,
+                payment_params
+            )
+        };
+
+        let mut actions = vec![];
 
-        Ok(ActionTransaction {
-            transaction,
-            message: Some("Payment successfully sent".to_string()),
+        // Preset buttons show what the receiver would get, which only makes sense for ExactIn
+        if swap_mode == SwapMode::ExactIn {
+            let token_mint_account = get_mint_account(&client, &token_mint_pubkey).await?;
+            let output_mint_account = get_mint_account(&client, &output_mint).await?;
+
+            let quote_previews = if token_mint_pubkey == output_mint {
+                PRESET_AMOUNTS
+                    .iter()
+                    .map(|amount| QuotePreview {
+                        amount: *amount,
+                        estimated_output: 10u64
+                            .checked_pow(output_mint_account.decimals as u32)
+                            .and_then(|base| amount.checked_mul(base))
+                            .map(|output| match &config.platform_fee {
+                                Some(platform_fee) => output - platform_fee.fee_amount(output),
+                                None => output,
+                            }),
+                    })
+                    .collect()
+            } else {
+                get_quote_previews(
+                    config.swap_provider.as_ref(),
+                    &token_mint_pubkey,
+                    &output_mint,
+                    token_mint_account.decimals,
+                    slippage_bps,
+                    config.platform_fee.map(|platform_fee| platform_fee.fee_bps),
+                )
+                .await
+            };
+
+            for quote_preview in quote_previews {
+                let label = match quote_preview.estimated_output {
+                    Some(estimated_output) => format!(
+                        "{} {} ≈ {} {}",
+                        quote_preview.amount,
+                        token_symbol,
+                        format_amount(estimated_output, output_mint_account.decimals),
+                        output_symbol
+                    ),
+                    None => format!("{} {}", quote_preview.amount, token_symbol),
+                };
+
+                actions.push(LinkedAction {
+                    label,
+                    href: href(&quote_preview.amount.to_string()),
+                    parameters: vec![],
+                });
+            }
+        }
+
+        actions.push(LinkedAction {
+            label: label.to_string(),
+            href: href("{amount}"),
+            parameters: vec![LinkedActionParameter {
+                label: format!("Amount in {}", amount_symbol),
+                name: "amount".to_string(),
+                required: true,
+            }],
+        });
+
+        let links = ActionLinks { actions };
+
+        Ok(ActionMetadata {
+            title: format!("Pay with {} using any Solana token", output_symbol),
+            description: description.to_string(),
+            icon: "https://raw.githubusercontent.com/leandrogavidia/files/main/payments-with-send-token.png".to_string(),
+            label: label.to_string(),
+            disabled: false,
+            error: None,
+            links: Some(links),
         })
     }
 
-    fn get_pay(ctx: Context<PayAction>) -> Result<ActionMetadata> {
+    // znap handlers can't read request headers, so rather than sending INVOICE_API_KEY the
+    // merchant signs `{timestamp}.{token_mint}/{receiver}?amount=..&output_mint=..&memo=..&expires_in=..`
+    // (absent values left empty) and passes the timestamp and signature
+    fn get_invoice(ctx: Context<InvoiceAction>) -> Result<ActionMetadata> {
         let token_mint = &ctx.params.token_mint;
         let receiver_address = &ctx.params.receiver;
+        let config = PayConfig::from_env()?;
 
-        let res = get_token_metadata(&token_mint, &ctx.env.rpc_url)
-            .await
-            .or_else(|_| Err(Error::from(ActionError::ErrorObtainingTokenMetadata)))?;
+        let request = format!(
+            "{}/{}?amount={}&output_mint={}&memo={}&expires_in={}",
+            token_mint,
+            receiver_address,
+            ctx.query.amount,
+            ctx.query.output_mint.as_deref().unwrap_or_default(),
+            ctx.query.memo.as_deref().unwrap_or_default(),
+            ctx.query.expires_in.map(|expires_in| expires_in.to_string()).unwrap_or_default()
+        );
+        config.check_invoice_request(&request, ctx.query.timestamp, &ctx.query.signature)?;
 
-        let token_symbol = res["result"]["token_info"]["symbol"].as_str().unwrap();
-        let label = "Send payment!";
-        let description = format!(
-            "Pay in {} and {} receives in SEND",
-            token_symbol, format_pubkey(&receiver_address.to_string(), 10)
+        let client = RpcClient::new(ctx.env.rpc_url.clone());
+        let receiver_pubkey = resolve_receiver(&client, receiver_address).await?;
+        parse_token_mint(token_mint)?;
+        let output_mint = config.output_mint(&ctx.query.output_mint)?;
+        let decimal_amount = DecimalAmount::parse(&ctx.query.amount)?;
+
+        if let Some(memo) = &ctx.query.memo {
+            memo_instruction(memo)?;
+        }
+
+        let output_mint_account = get_mint_account(&client, &output_mint).await?;
+        let amount = decimal_amount.to_base_units(output_mint_account.decimals)?;
+
+        let invoice = Invoice::new(
+            &receiver_pubkey,
+            &output_mint,
+            amount,
+            ctx.query.memo.clone(),
+            ctx.query.expires_in.unwrap_or(DEFAULT_INVOICE_TTL_SECS),
         );
-        let amount = "{amount}";
-        let links = ActionLinks {
-            actions: vec![LinkedAction {
-                label: label.to_string(),
-                href: format!("/api/pay/{}/{}?amount={}", token_mint, receiver_address, amount),
-                parameters: vec![LinkedActionParameter {
-                    label: format!("Amount in {}", token_symbol),
-                    name: "amount".to_string(),
-                    required: true,
+        let invoice_token = config.sign_invoice(&invoice)?;
+
+        let pay_href = format!("/api/pay/{}/{}?invoice={}", token_mint, receiver_address, invoice_token);
+
+        // The pay link is returned as the only linked action, clients read it from there
+        Ok(ActionMetadata {
+            title: "Invoice".to_string(),
+            description: format!(
+                "Invoice for {} {} to {}, expiring at unix time {}",
+                format_amount(amount, output_mint_account.decimals),
+                get_token_symbol(&client, &ctx.env.rpc_url, &output_mint).await,
+                receiver_address,
+                invoice.expires_at
+            ),
+            icon: "https://raw.githubusercontent.com/leandrogavidia/files/main/payments-with-send-token.png".to_string(),
+            label: "Pay invoice".to_string(),
+            disabled: false,
+            error: None,
+            links: Some(ActionLinks {
+                actions: vec![LinkedAction {
+                    label: "Pay invoice".to_string(),
+                    href: pay_href,
+                    parameters: vec![],
                 }],
-            }],
+            }),
+        })
+    }
+
+    // znap GET handlers can only return ActionMetadata, so as a workaround the verdict is
+    // serialized as JSON into the description and the status is repeated in the label
+    fn get_verify(ctx: Context<VerifyAction>) -> Result<ActionMetadata> {
+        let client = RpcClient::new(ctx.env.rpc_url.clone());
+        let receiver_pubkey = resolve_receiver(&client, &ctx.params.receiver).await?;
+        let config = PayConfig::from_env()?;
+        let output_mint = config.output_mint(&ctx.query.output_mint)?;
+
+        let reference = match &ctx.query.reference {
+            Some(_) => Some(parse_reference(&ctx.query.reference)?),
+            None => None,
+        };
+
+        // A given signature must still carry the order's reference, checked in the verdict
+        let signature = match (&ctx.query.signature, &reference) {
+            (Some(signature), _) => parse_signature(signature)?,
+            (None, Some(reference)) => find_reference_signature(&client, reference).await?,
+            (None, None) => return Err(Error::from(ActionError::MissingPaymentIdentifier)),
+        };
+
+        let expected_amount = match &ctx.query.amount {
+            Some(amount) => {
+                let output_mint_account = get_mint_account(&client, &output_mint).await?;
+                Some(DecimalAmount::parse(amount)?.to_base_units(output_mint_account.decimals)?)
+            }
+            None => None,
+        };
+
+        let expected_payment = ExpectedPayment {
+            receiver: receiver_pubkey,
+            mint: output_mint,
+            reference,
+            amount: expected_amount,
         };
+        let verdict = verify_payment(&client, &signature, &expected_payment).await?;
+
+        let description = serde_json::to_string(&verdict)
+            .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
 
         Ok(ActionMetadata {
-            title: "Pay with SEND using any Solana token".to_string(),
-            description: description.to_string(),
+            title: "Payment verification".to_string(),
+            description,
             icon: "https://raw.githubusercontent.com/leandrogavidia/files/main/payments-with-send-token.png".to_string(),
-            label: label.to_string(),
-            disabled: false,
+            label: format!("{:?}", verdict.status),
+            disabled: true,
             error: None,
-            links: Some(links),
+            links: None,
         })
     }
 }
 
 #[derive(Action)]
-#[query(amount: f32)]
+#[query(
+    amount: Option<String>,
+    output_mint: Option<String>,
+    mode: Option<String>,
+    slippage_bps: Option<u16>,
+    max_price_impact_pct: Option<f64>,
+    reference: Option<String>,
+    memo: Option<String>,
+    invoice: Option<String>,
+    splits: Option<String>
+)]
+#[params(token_mint: String, receiver: String)]
+pub struct PayAction;
+
+#[derive(Action)]
+#[query(
+    timestamp: u64,
+    signature: String,
+    amount: String,
+    output_mint: Option<String>,
+    memo: Option<String>,
+    expires_in: Option<u64>
+)]
 #[params(token_mint: String, receiver: String)]
-pub struct PayAction;
\ No newline at end of file
+pub struct InvoiceAction;
+
+#[derive(Action)]
+#[query(
+    signature: Option<String>,
+    reference: Option<String>,
+    output_mint: Option<String>,
+    amount: Option<String>
+)]
+#[params(receiver: String)]
+pub struct VerifyAction;
\ No newline at end of file
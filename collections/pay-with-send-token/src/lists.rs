@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashSet,
+    env, fs,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+// The last lists read from PAY_LISTS_PATH, reloaded whenever the file changes
+static LISTS_CACHE: OnceLock<Mutex<Option<CachedLists>>> = OnceLock::new();
+
+struct CachedLists {
+    path: String,
+    modified: SystemTime,
+    lists: Arc<PaymentLists>,
+}
+
+// The file is JSON, e.g. {"deniedReceivers": ["..."], "allowedTokens": ["..."]}. Without
+// allowedTokens every token is allowed, native SOL is its wrapped mint
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListsFile {
+    #[serde(default)]
+    denied_receivers: Vec<String>,
+    allowed_tokens: Option<Vec<String>>,
+}
+
+#[derive(Default)]
+pub struct PaymentLists {
+    denied_receivers: HashSet<Pubkey>,
+    allowed_tokens: Option<HashSet<Pubkey>>,
+}
+
+pub enum ListRejection {
+    ReceiverDenied,
+    TokenNotAllowed,
+}
+
+impl ListRejection {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ListRejection::ReceiverDenied => "Payments to this receiver are not allowed",
+            ListRejection::TokenNotAllowed => "This token is not allowed for payments",
+        }
+    }
+}
+
+impl From<ListRejection> for Error {
+    fn from(rejection: ListRejection) -> Error {
+        match rejection {
+            ListRejection::ReceiverDenied => Error::from(ActionError::ReceiverDenied),
+            ListRejection::TokenNotAllowed => Error::from(ActionError::TokenNotAllowed),
+        }
+    }
+}
+
+impl PaymentLists {
+    pub fn rejection(&self, receivers: &[Pubkey], mints: &[Pubkey]) -> Option<ListRejection> {
+        if let Some(receiver) = receivers
+            .iter()
+            .find(|receiver| self.denied_receivers.contains(receiver))
+        {
+            println!("Receiver {} is on the deny list", receiver);
+            return Some(ListRejection::ReceiverDenied);
+        }
+
+        if let Some(allowed_tokens) = &self.allowed_tokens {
+            if let Some(mint) = mints.iter().find(|mint| !allowed_tokens.contains(mint)) {
+                println!("Token {} is not on the allow list", mint);
+                return Some(ListRejection::TokenNotAllowed);
+            }
+        }
+
+        None
+    }
+
+    pub fn check(&self, receivers: &[Pubkey], mints: &[Pubkey]) -> Result<()> {
+        match self.rejection(receivers, mints) {
+            Some(rejection) => Err(Error::from(rejection)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Keeps serving the last good lists when the file can't be read or parsed
+pub fn payment_lists() -> Result<Arc<PaymentLists>> {
+    let path = match env::var("PAY_LISTS_PATH") {
+        Ok(path) => path,
+        Err(_) => return Ok(Arc::new(PaymentLists::default())),
+    };
+
+    let cache = LISTS_CACHE.get_or_init(|| Mutex::new(None));
+    let mut cache = cache
+        .lock()
+        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+
+    let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+
+    if let (Some(cached), Ok(modified)) = (cache.as_ref(), &modified) {
+        if cached.path == path && cached.modified == *modified {
+            return Ok(cached.lists.clone());
+        }
+    }
+
+    let loaded_lists = match modified {
+        Ok(modified) => read_lists(&path).map(|lists| (modified, lists)),
+        Err(err) => Err(err.to_string()),
+    };
+
+    match loaded_lists {
+        Ok((modified, lists)) => {
+            let lists = Arc::new(lists);
+            *cache = Some(CachedLists {
+                path,
+                modified,
+                lists: lists.clone(),
+            });
+
+            Ok(lists)
+        }
+        Err(err) => {
+            println!("Error loading payment lists from {}: {}", path, err);
+
+            match cache.as_ref() {
+                Some(cached) if cached.path == path => Ok(cached.lists.clone()),
+                _ => Err(Error::from(ActionError::ErrorLoadingPaymentLists)),
+            }
+        }
+    }
+}
+
+fn read_lists(path: &str) -> std::result::Result<PaymentLists, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let lists_file =
+        serde_json::from_str::<ListsFile>(&contents).map_err(|err| err.to_string())?;
+
+    let parse_pubkeys = |pubkeys: Vec<String>| {
+        pubkeys
+            .iter()
+            .map(|pubkey| Pubkey::from_str(pubkey).map_err(|_| format!("invalid pubkey {}", pubkey)))
+            .collect::<std::result::Result<HashSet<Pubkey>, String>>()
+    };
+
+    Ok(PaymentLists {
+        denied_receivers: parse_pubkeys(lists_file.denied_receivers)?,
+        allowed_tokens: match lists_file.allowed_tokens {
+            Some(allowed_tokens) => Some(parse_pubkeys(allowed_tokens)?),
+            None => None,
+        },
+    })
+}
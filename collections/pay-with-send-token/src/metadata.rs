@@ -0,0 +1,140 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::helius_api;
+use crate::token::{is_native_sol, NATIVE_SOL_ALIAS};
+use crate::utils::format_pubkey;
+
+const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const METADATA_SEED: &[u8; 8] = b"metadata";
+
+pub struct TokenMetadata {
+    pub symbol: String,
+}
+
+// Reads the Metaplex metadata account, falling back to Helius for mints without one
+pub async fn get_token_metadata(
+    client: &RpcClient,
+    rpc_url: &str,
+    mint: &Pubkey,
+) -> Result<TokenMetadata> {
+    if is_native_sol(mint) {
+        return Ok(TokenMetadata {
+            symbol: NATIVE_SOL_ALIAS.to_string(),
+        });
+    }
+
+    let (metadata_address, _metadata_bump) = Pubkey::find_program_address(
+        &[METADATA_SEED, METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    );
+
+    if let Ok(account) = client.get_account(&metadata_address).await {
+        if let Some(metadata) = parse_metaplex_metadata(&account.data) {
+            return Ok(metadata);
+        }
+    }
+
+    let asset = helius_api::get_token_metadata(&mint.to_string(), rpc_url)
+        .await
+        .or_else(|_| Err(Error::from(ActionError::TokenSymbolUnavailable)))?;
+
+    let symbol = asset
+        .token_info
+        .and_then(|token_info| token_info.symbol)
+        .or(asset
+            .content
+            .and_then(|content| content.metadata)
+            .and_then(|metadata| metadata.symbol))
+        .filter(|symbol| !symbol.is_empty())
+        .ok_or(Error::from(ActionError::TokenSymbolUnavailable))?;
+
+    Ok(TokenMetadata { symbol })
+}
+
+// A plain RPC or a test validator may have neither Metaplex metadata nor Helius, so
+// the truncated mint stands in for the symbol
+pub async fn get_token_symbol(client: &RpcClient, rpc_url: &str, mint: &Pubkey) -> String {
+    match get_token_metadata(client, rpc_url, mint).await {
+        Ok(metadata) => metadata.symbol,
+        Err(_) => {
+            println!("No symbol found for mint {}", mint);
+            format_pubkey(&mint.to_string(), 8)
+        }
+    }
+}
+
+fn parse_metaplex_metadata(data: &[u8]) -> Option<TokenMetadata> {
+    // Skip the key, the update authority and the mint
+    let mut offset = 1 + 32 + 32;
+
+    // The name comes first, only its length is needed to reach the symbol
+    read_string(data, &mut offset)?;
+    let symbol = read_string(data, &mut offset)?;
+
+    if symbol.is_empty() {
+        return None;
+    }
+
+    Some(TokenMetadata { symbol })
+}
+
+// Borsh strings are length prefixed, and Metaplex pads them with null bytes
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let length_bytes: [u8; 4] = data.get(*offset..*offset + 4)?.try_into().ok()?;
+    let length = u32::from_le_bytes(length_bytes) as usize;
+    *offset += 4;
+
+    let bytes = data.get(*offset..*offset + length)?;
+    *offset += length;
+
+    Some(
+        String::from_utf8_lossy(bytes)
+            .trim_end_matches('\0')
+            .trim()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(value: &str, padded_length: usize) -> Vec<u8> {
+        let mut bytes = (padded_length as u32).to_le_bytes().to_vec();
+        bytes.extend(value.as_bytes());
+        bytes.resize(4 + padded_length, 0);
+        bytes
+    }
+
+    fn metadata_account(name: &str, symbol: &str) -> Vec<u8> {
+        let mut data = vec![4];
+        data.extend([1; 32]);
+        data.extend([2; 32]);
+        data.extend(borsh_string(name, 32));
+        data.extend(borsh_string(symbol, 10));
+        data.extend(borsh_string("https://example.com/token.json", 200));
+        data
+    }
+
+    #[test]
+    fn parses_the_symbol_after_a_padded_name() {
+        let metadata = parse_metaplex_metadata(&metadata_account("Send", "SEND")).unwrap();
+
+        assert_eq!(metadata.symbol, "SEND");
+    }
+
+    #[test]
+    fn rejects_an_empty_symbol() {
+        assert!(parse_metaplex_metadata(&metadata_account("Send", "")).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let data = metadata_account("Send", "SEND");
+
+        assert!(parse_metaplex_metadata(&data[..1 + 32 + 32 + 4 + 10]).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+use common::{
+    compute_budget::set_compute_budget,
+    policy::check_policy,
+    programs::JUPITER_PROGRAM_ID,
+    simulation::check_simulation,
+    transaction::{finalize_transaction, FinalizedTransaction},
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_program};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::platform_fee::PlatformFee;
+use crate::reference::{add_reference, memo_instruction};
+use crate::splits::{
+    close_native_account_instruction, create_token_account_instructions,
+    native_transfer_instructions, split_payments, token_transfer_instructions, Split,
+};
+use crate::swap_provider::{
+    Quote, QuoteError, QuoteRequest, RouteLimits, SwapMode, SwapProvider, ROUTE_LIMITS,
+};
+use crate::token::{is_native_sol, MintAccount};
+use crate::utils::fits_in_packet;
+
+// A payment resolved from the query or an invoice, ready to be turned into instructions
+pub struct Payment {
+    pub payer: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_mint_account: MintAccount,
+    pub output_mint: Pubkey,
+    pub output_mint_account: MintAccount,
+    pub swap_mode: SwapMode,
+    // In base units of the input token for ExactIn, of the output token for ExactOut
+    pub amount: u64,
+    pub splits: Vec<Split>,
+    pub reference: Pubkey,
+    pub memo: Option<String>,
+    pub slippage_bps: u16,
+    pub max_price_impact_pct: f64,
+    pub platform_fee: Option<PlatformFee>,
+}
+
+pub struct PaymentInstructions {
+    pub instructions: Vec<Instruction>,
+    // The most the payer can spend, in base units of the input token
+    pub max_input_amount: u64,
+}
+
+// Builds the payment, asking for smaller swap routes until it fits in a single transaction
+pub async fn build_payment(
+    client: &RpcClient,
+    swap_provider: &dyn SwapProvider,
+    payment: &Payment,
+) -> Result<PaymentInstructions> {
+    // Paying in the receiving token needs no swap, just transfers
+    if payment.token_mint == payment.output_mint {
+        let mut payment_instructions = build_transfer_leg(client, payment).await?;
+        add_memo(&mut payment_instructions, payment)?;
+
+        // No route to simplify here, only the receivers and the memo take up space
+        if !fits_in_packet(&payment_instructions.instructions, &payment.payer)? {
+            return Err(Error::from(ActionError::PaymentTooLarge));
+        }
+
+        return Ok(payment_instructions);
+    }
+
+    for (attempt, route_limits) in ROUTE_LIMITS.iter().enumerate() {
+        // A tighter route may not exist, which still means the payment doesn't fit
+        let quote = match swap_provider.quote(&quote_request(payment, route_limits)).await {
+            Ok(quote) => quote,
+            Err(QuoteError::NoRoute) if attempt > 0 => break,
+            Err(err) => return Err(Error::from(err)),
+        };
+
+        let mut payment_instructions = build_swap_leg(swap_provider, payment, quote).await?;
+        add_memo(&mut payment_instructions, payment)?;
+
+        if fits_in_packet(&payment_instructions.instructions, &payment.payer)? {
+            return Ok(payment_instructions);
+        }
+
+        println!("Transaction too large with {:?}, re-quoting", route_limits);
+    }
+
+    Err(Error::from(ActionError::TransactionTooLarge))
+}
+
+// Fits the compute budget, sets the blockhash and checks the result before it reaches the wallet
+pub async fn finalize_payment(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    rpc_url: &str,
+    max_priority_fee_lamports: u64,
+) -> Result<FinalizedTransaction> {
+    // The swap's compute budget instructions are replaced by ones fitted to the whole transaction
+    let instructions =
+        set_compute_budget(instructions, payer, rpc_url, max_priority_fee_lamports).await;
+
+    let finalized_transaction = finalize_transaction(&instructions, payer, rpc_url).await?;
+
+    check_policy(
+        &finalized_transaction.transaction,
+        payer,
+        &[
+            system_program::id(),
+            spl_token::ID,
+            spl_token_2022::ID,
+            spl_associated_token_account::ID,
+            spl_memo::ID,
+            JUPITER_PROGRAM_ID,
+        ],
+        "PAY_ALLOWED_PROGRAM_IDS",
+    )?;
+    check_simulation(&finalized_transaction.transaction, rpc_url).await?;
+
+    Ok(finalized_transaction)
+}
+
+fn quote_request(payment: &Payment, route_limits: &RouteLimits) -> QuoteRequest {
+    QuoteRequest {
+        input_mint: payment.token_mint,
+        output_mint: payment.output_mint,
+        amount: payment.amount,
+        swap_mode: payment.swap_mode,
+        slippage_bps: payment.slippage_bps,
+        platform_fee_bps: payment.platform_fee.map(|platform_fee| platform_fee.fee_bps),
+        route_limits: *route_limits,
+    }
+}
+
+fn add_memo(payment_instructions: &mut PaymentInstructions, payment: &Payment) -> Result<()> {
+    if let Some(memo) = &payment.memo {
+        payment_instructions.instructions.push(memo_instruction(memo)?);
+    }
+
+    Ok(())
+}
+
+// Transfers from the payer to each receiver, with the platform fee as a separate transfer
+async fn build_transfer_leg(client: &RpcClient, payment: &Payment) -> Result<PaymentInstructions> {
+    let mut instructions = create_token_account_instructions(
+        &payment.payer,
+        &payment.splits,
+        &payment.output_mint,
+        &payment.output_mint_account,
+    );
+
+    let fee_amount = match &payment.platform_fee {
+        Some(platform_fee) => platform_fee.fee_amount(payment.amount),
+        None => 0,
+    };
+    let amount = match (payment.swap_mode, &payment.platform_fee) {
+        (SwapMode::ExactIn, Some(platform_fee)) => platform_fee.amount_after_fee(payment.amount)?,
+        _ => payment.amount,
+    };
+
+    let payments = split_payments(
+        &payment.splits,
+        amount,
+        &payment.output_mint,
+        &payment.output_mint_account.token_program_id,
+    );
+
+    let mut transfer_instructions = if is_native_sol(&payment.token_mint) {
+        native_transfer_instructions(&payment.payer, &payments)?
+    } else {
+        let payer_ata_address = get_associated_token_address_with_program_id(
+            &payment.payer,
+            &payment.token_mint,
+            &payment.token_mint_account.token_program_id,
+        );
+
+        token_transfer_instructions(
+            &payer_ata_address,
+            &payment.payer,
+            &payment.token_mint,
+            &payment.token_mint_account,
+            &payments,
+        )?
+    };
+    add_reference(&mut transfer_instructions[0], &payment.reference);
+
+    instructions.extend(transfer_instructions);
+
+    if let Some(platform_fee) = &payment.platform_fee {
+        instructions.extend(
+            platform_fee
+                .transfer_instructions(
+                    client,
+                    &payment.payer,
+                    &payment.token_mint,
+                    &payment.token_mint_account,
+                    fee_amount,
+                )
+                .await?,
+        );
+    }
+
+    Ok(PaymentInstructions {
+        instructions,
+        max_input_amount: amount.saturating_add(fee_amount),
+    })
+}
+
+// Swaps into the receiver's account, or into the payer's and fans out from there to several
+async fn build_swap_leg(
+    swap_provider: &dyn SwapProvider,
+    payment: &Payment,
+    quote: Quote,
+) -> Result<PaymentInstructions> {
+    quote.check_price_impact(payment.max_price_impact_pct)?;
+
+    let mut instructions = create_token_account_instructions(
+        &payment.payer,
+        &payment.splits,
+        &payment.output_mint,
+        &payment.output_mint_account,
+    );
+
+    // An ExactIn swap has a single receiver, which gets the whole output
+    let swapped_amount = match payment.swap_mode {
+        SwapMode::ExactIn => quote.other_amount_threshold,
+        SwapMode::ExactOut => payment.amount,
+    };
+    let payments = split_payments(
+        &payment.splits,
+        swapped_amount,
+        &payment.output_mint,
+        &payment.output_mint_account.token_program_id,
+    );
+
+    let payer_output_ata_address = get_associated_token_address_with_program_id(
+        &payment.payer,
+        &payment.output_mint,
+        &payment.output_mint_account.token_program_id,
+    );
+    let swap_destination = if payments.len() == 1 {
+        payments[0].token_account
+    } else {
+        instructions.push(create_associated_token_account_idempotent(
+            &payment.payer,
+            &payment.payer,
+            &payment.output_mint,
+            &payment.output_mint_account.token_program_id,
+        ));
+
+        payer_output_ata_address
+    };
+
+    // Jupiter takes the fee from the output in ExactIn and from the input in ExactOut
+    let fee_token_account = match &payment.platform_fee {
+        Some(platform_fee) => {
+            let (fee_mint, fee_mint_account) = match payment.swap_mode {
+                SwapMode::ExactIn => (&payment.output_mint, &payment.output_mint_account),
+                SwapMode::ExactOut => (&payment.token_mint, &payment.token_mint_account),
+            };
+
+            instructions.push(platform_fee.create_fee_token_account_instruction(
+                &payment.payer,
+                fee_mint,
+                fee_mint_account,
+            ));
+
+            Some(platform_fee.fee_token_account(fee_mint, fee_mint_account))
+        }
+        None => None,
+    };
+
+    let max_input_amount = match payment.swap_mode {
+        SwapMode::ExactIn => payment.amount,
+        SwapMode::ExactOut => quote.other_amount_threshold,
+    };
+
+    let mut swap_instructions = swap_provider
+        .swap_instructions(&payment.payer, &swap_destination, fee_token_account, quote)
+        .await?;
+    add_reference(&mut swap_instructions.swap_instruction, &payment.reference);
+
+    instructions.extend(swap_instructions.into_instructions());
+
+    if payments.len() > 1 {
+        instructions.extend(token_transfer_instructions(
+            &payer_output_ata_address,
+            &payment.payer,
+            &payment.output_mint,
+            &payment.output_mint_account,
+            &payments,
+        )?);
+        instructions.extend(close_native_account_instruction(
+            &payment.payer,
+            &payer_output_ata_address,
+            &payment.output_mint,
+        )?);
+    }
+
+    Ok(PaymentInstructions {
+        instructions,
+        max_input_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_swap_provider::FakeSwapProvider;
+    use spl_token::native_mint;
+
+    const AMOUNT: u64 = 5_000_000;
+
+    fn payment(token_mint: Pubkey, output_mint: Pubkey, reference: Pubkey) -> Payment {
+        let mint_account = |mint: &Pubkey| {
+            let decimals = if is_native_sol(mint) { 9 } else { 6 };
+            MintAccount::without_transfer_fee(spl_token::ID, decimals)
+        };
+
+        Payment {
+            payer: Pubkey::new_unique(),
+            token_mint,
+            token_mint_account: mint_account(&token_mint),
+            output_mint,
+            output_mint_account: mint_account(&output_mint),
+            swap_mode: SwapMode::ExactOut,
+            amount: AMOUNT,
+            splits: vec![Split {
+                receiver: Pubkey::new_unique(),
+                bps: 10_000,
+            }],
+            reference,
+            memo: Some("Order 42".to_string()),
+            slippage_bps: 50,
+            max_price_impact_pct: 5.0,
+            platform_fee: None,
+        }
+    }
+
+    fn client() -> RpcClient {
+        RpcClient::new_mock("succeeds".to_string())
+    }
+
+    fn quoted_max_accounts(swap_provider: &FakeSwapProvider) -> Vec<u8> {
+        swap_provider
+            .quoted_route_limits
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|route_limits| route_limits.max_accounts)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn pays_through_the_swap_provider() {
+        let reference = Pubkey::new_unique();
+        let payment = payment(native_mint::ID, Pubkey::new_unique(), reference);
+        let swap_provider = FakeSwapProvider::new(&[4]);
+
+        let payment_instructions =
+            build_payment(&client(), &swap_provider, &payment).await.ok().unwrap();
+        let instructions = &payment_instructions.instructions;
+
+        let swap_instruction = instructions
+            .iter()
+            .find(|instruction| instruction.program_id == JUPITER_PROGRAM_ID)
+            .unwrap();
+        assert_eq!(swap_instruction.accounts.last().unwrap().pubkey, reference);
+        assert_eq!(instructions.last().unwrap().program_id, spl_memo::ID);
+        assert_eq!(payment_instructions.max_input_amount, AMOUNT);
+        assert_eq!(quoted_max_accounts(&swap_provider), vec![18]);
+    }
+
+    #[tokio::test]
+    async fn re_quotes_tighter_routes_until_the_payment_fits() {
+        let payment = payment(native_mint::ID, Pubkey::new_unique(), Pubkey::new_unique());
+        let swap_provider = FakeSwapProvider::new(&[40, 4]);
+
+        let payment_instructions = build_payment(&client(), &swap_provider, &payment).await;
+
+        assert!(payment_instructions.is_ok());
+        assert_eq!(quoted_max_accounts(&swap_provider), vec![18, 14]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_no_tighter_route_exists() {
+        let payment = payment(native_mint::ID, Pubkey::new_unique(), Pubkey::new_unique());
+        let swap_provider = FakeSwapProvider::new(&[40]);
+
+        let payment_instructions = build_payment(&client(), &swap_provider, &payment).await;
+
+        assert!(payment_instructions.is_err());
+        assert_eq!(quoted_max_accounts(&swap_provider), vec![18, 14]);
+    }
+
+    #[tokio::test]
+    async fn transfers_without_quoting_in_the_receiving_token() {
+        let reference = Pubkey::new_unique();
+        let payment = payment(native_mint::ID, native_mint::ID, reference);
+        let swap_provider = FakeSwapProvider::new(&[]);
+
+        let payment_instructions =
+            build_payment(&client(), &swap_provider, &payment).await.ok().unwrap();
+
+        let transfer_instruction = payment_instructions
+            .instructions
+            .iter()
+            .find(|instruction| instruction.program_id == system_program::id())
+            .unwrap();
+        assert_eq!(transfer_instruction.accounts.last().unwrap().pubkey, reference);
+        assert_eq!(payment_instructions.max_input_amount, AMOUNT);
+        assert!(quoted_max_accounts(&swap_provider).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_instruction};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::instruction::transfer_checked;
+use std::{env, str::FromStr};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::token::{is_native_sol, MintAccount};
+
+const BPS_DENOMINATOR: u128 = 10_000;
+// Anything above 10% is far more likely a typo than an intended fee
+const MAX_PLATFORM_FEE_BPS: u16 = 1_000;
+
+// The service fee taken on every payment, set with PLATFORM_FEE_BPS and PLATFORM_FEE_ACCOUNT
+#[derive(Clone, Copy, Debug)]
+pub struct PlatformFee {
+    pub fee_bps: u16,
+    // Wallet of the fee recipient, fees land in its associated token accounts
+    pub fee_account: Pubkey,
+}
+
+impl PlatformFee {
+    pub fn from_env() -> Result<Option<PlatformFee>> {
+        match (env::var("PLATFORM_FEE_BPS"), env::var("PLATFORM_FEE_ACCOUNT")) {
+            (Ok(fee_bps), Ok(fee_account)) => {
+                PlatformFee::parse(&fee_bps, &fee_account).or_else(|err| Err(Error::from(err)))
+            }
+            (Err(_), Err(_)) => Ok(None),
+            _ => Err(Error::from(ActionError::InvalidPlatformFee)),
+        }
+    }
+
+    fn parse(
+        fee_bps: &str,
+        fee_account: &str,
+    ) -> std::result::Result<Option<PlatformFee>, ActionError> {
+        let fee_bps = fee_bps.parse::<u16>().or(Err(ActionError::InvalidPlatformFee))?;
+        let fee_account = Pubkey::from_str(fee_account).or(Err(ActionError::InvalidPlatformFee))?;
+
+        if fee_bps == 0 {
+            return Ok(None);
+        }
+
+        if fee_bps > MAX_PLATFORM_FEE_BPS {
+            return Err(ActionError::InvalidPlatformFee);
+        }
+
+        Ok(Some(PlatformFee {
+            fee_bps,
+            fee_account,
+        }))
+    }
+
+    pub fn fee_amount(&self, amount: u64) -> u64 {
+        (amount as u128 * self.fee_bps as u128 / BPS_DENOMINATOR) as u64
+    }
+
+    // What the receiver keeps when the fee comes out of the amount, never nothing
+    pub fn amount_after_fee(&self, amount: u64) -> Result<u64> {
+        match amount - self.fee_amount(amount) {
+            0 => Err(Error::from(ActionError::AmountTooSmall)),
+            amount => Ok(amount),
+        }
+    }
+
+    pub fn fee_pct(&self) -> f64 {
+        self.fee_bps as f64 / 100.0
+    }
+
+    pub fn fee_token_account(&self, mint: &Pubkey, mint_account: &MintAccount) -> Pubkey {
+        get_associated_token_address_with_program_id(
+            &self.fee_account,
+            mint,
+            &mint_account.token_program_id,
+        )
+    }
+
+    // Jupiter only checks that the fee token account exists when the swap runs
+    pub fn create_fee_token_account_instruction(
+        &self,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        mint_account: &MintAccount,
+    ) -> Instruction {
+        create_associated_token_account_idempotent(
+            payer,
+            &self.fee_account,
+            mint,
+            &mint_account.token_program_id,
+        )
+    }
+
+    // A direct fee payment for the no-swap path, in lamports for native SOL
+    pub async fn transfer_instructions(
+        &self,
+        client: &RpcClient,
+        payer: &Pubkey,
+        mint: &Pubkey,
+        mint_account: &MintAccount,
+        fee_amount: u64,
+    ) -> Result<Vec<Instruction>> {
+        if fee_amount == 0 {
+            return Ok(vec![]);
+        }
+
+        if is_native_sol(mint) {
+            self.check_fee_account_funded(client, fee_amount).await?;
+
+            return Ok(vec![system_instruction::transfer(
+                payer,
+                &self.fee_account,
+                fee_amount,
+            )]);
+        }
+
+        let payer_token_account =
+            get_associated_token_address_with_program_id(payer, mint, &mint_account.token_program_id);
+
+        let transfer_instruction = transfer_checked(
+            &mint_account.token_program_id,
+            &payer_token_account,
+            mint,
+            &self.fee_token_account(mint, mint_account),
+            payer,
+            &[],
+            fee_amount,
+            mint_account.decimals,
+        )
+        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+
+        Ok(vec![
+            self.create_fee_token_account_instruction(payer, mint, mint_account),
+            transfer_instruction,
+        ])
+    }
+
+    // A system transfer can't create an account with less than its rent-exempt minimum
+    async fn check_fee_account_funded(&self, client: &RpcClient, fee_amount: u64) -> Result<()> {
+        let balance = client
+            .get_balance(&self.fee_account)
+            .await
+            .or_else(|_| Err(Error::from(ActionError::UpstreamUnavailable)))?;
+
+        if balance > 0 {
+            return Ok(());
+        }
+
+        let minimum_balance = client
+            .get_minimum_balance_for_rent_exemption(0)
+            .await
+            .or_else(|_| Err(Error::from(ActionError::UpstreamUnavailable)))?;
+
+        if fee_amount < minimum_balance {
+            println!(
+                "Platform fee account {} is unfunded and a {} lamports fee can't open it",
+                self.fee_account, fee_amount
+            );
+            return Err(Error::from(ActionError::PlatformFeeAccountUnfunded));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEE_ACCOUNT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn platform_fee(fee_bps: u16) -> PlatformFee {
+        PlatformFee {
+            fee_bps,
+            fee_account: Pubkey::from_str(FEE_ACCOUNT).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parses_a_valid_fee() {
+        let fee = PlatformFee::parse("50", FEE_ACCOUNT);
+
+        assert!(matches!(fee, Ok(Some(PlatformFee { fee_bps: 50, .. }))));
+    }
+
+    #[test]
+    fn treats_a_zero_fee_as_no_fee() {
+        assert!(matches!(PlatformFee::parse("0", FEE_ACCOUNT), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_fees_above_the_cap() {
+        assert!(matches!(PlatformFee::parse("1000", FEE_ACCOUNT), Ok(Some(_))));
+        assert!(matches!(
+            PlatformFee::parse("1001", FEE_ACCOUNT),
+            Err(ActionError::InvalidPlatformFee)
+        ));
+        assert!(matches!(
+            PlatformFee::parse("10000", FEE_ACCOUNT),
+            Err(ActionError::InvalidPlatformFee)
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_settings() {
+        assert!(matches!(
+            PlatformFee::parse("-1", FEE_ACCOUNT),
+            Err(ActionError::InvalidPlatformFee)
+        ));
+        assert!(matches!(
+            PlatformFee::parse("50", "not-a-pubkey"),
+            Err(ActionError::InvalidPlatformFee)
+        ));
+    }
+
+    #[test]
+    fn rounds_the_fee_down() {
+        let fee = platform_fee(25);
+
+        assert_eq!(fee.fee_amount(1_000_000), 2_500);
+        assert_eq!(fee.fee_amount(399), 0);
+        assert_eq!(fee.fee_amount(400), 1);
+        assert_eq!(fee.fee_amount(u64::MAX), 46_116_860_184_273_879);
+        assert_eq!(fee.fee_pct(), 0.25);
+    }
+
+    #[test]
+    fn leaves_the_receiver_the_rest() {
+        let fee = platform_fee(1_000);
+
+        assert!(matches!(fee.amount_after_fee(1_000), Ok(900)));
+        assert!(matches!(fee.amount_after_fee(1), Ok(1)));
+        assert!(fee.amount_after_fee(0).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use futures::future::join_all;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::swap_provider::{QuoteRequest, SwapMode, SwapProvider, ROUTE_LIMITS};
+
+pub const PRESET_AMOUNTS: [u64; 3] = [5, 10, 25];
+const QUOTE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+type QuoteKey = (Pubkey, Pubkey, u64, u16, Option<u16>);
+
+// Recent estimated outputs, so repeated unfurls of the same blink don't hit Jupiter every time
+static QUOTE_CACHE: OnceLock<Mutex<HashMap<QuoteKey, (Instant, u64)>>> = OnceLock::new();
+
+pub struct QuotePreview {
+    pub amount: u64,
+    pub estimated_output: Option<u64>,
+}
+
+// Quotes every preset amount concurrently, a failed quote just leaves its estimate empty
+pub async fn get_quote_previews(
+    swap_provider: &dyn SwapProvider,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    input_decimals: u8,
+    slippage_bps: u16,
+    platform_fee_bps: Option<u16>,
+) -> Vec<QuotePreview> {
+    let quotes = PRESET_AMOUNTS.iter().map(|amount| async move {
+        let base_amount = 10u64
+            .checked_pow(input_decimals as u32)
+            .and_then(|base| amount.checked_mul(base));
+
+        let estimated_output = match base_amount {
+            Some(base_amount) => {
+                get_estimated_output(
+                    swap_provider,
+                    input_mint,
+                    output_mint,
+                    base_amount,
+                    slippage_bps,
+                    platform_fee_bps,
+                )
+                .await
+            }
+            None => None,
+        };
+
+        QuotePreview {
+            amount: *amount,
+            estimated_output,
+        }
+    });
+
+    join_all(quotes).await
+}
+
+async fn get_estimated_output(
+    swap_provider: &dyn SwapProvider,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    amount: u64,
+    slippage_bps: u16,
+    platform_fee_bps: Option<u16>,
+) -> Option<u64> {
+    let key = (*input_mint, *output_mint, amount, slippage_bps, platform_fee_bps);
+    let cache = QUOTE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+    if let Some((quoted_at, out_amount)) = cache.lock().ok()?.get(&key) {
+        if quoted_at.elapsed() < QUOTE_CACHE_TTL {
+            return Some(*out_amount);
+        }
+    }
+
+    let quote_request = QuoteRequest {
+        input_mint: *input_mint,
+        output_mint: *output_mint,
+        amount,
+        swap_mode: SwapMode::ExactIn,
+        slippage_bps,
+        platform_fee_bps,
+        route_limits: ROUTE_LIMITS[0],
+    };
+
+    let out_amount = swap_provider.quote(&quote_request).await.ok()?.out_amount;
+
+    let mut cache = cache.lock().ok()?;
+    cache.retain(|_, (quoted_at, _)| quoted_at.elapsed() < QUOTE_CACHE_TTL);
+    cache.insert(key, (Instant::now(), out_amount));
+
+    Some(out_amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_swap_provider::FakeSwapProvider;
+
+    #[tokio::test]
+    async fn quotes_every_preset_amount() {
+        let swap_provider = FakeSwapProvider::new(&[4; PRESET_AMOUNTS.len()]);
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let quote_previews =
+            get_quote_previews(&swap_provider, &input_mint, &output_mint, 6, 50, None).await;
+
+        let estimated_outputs: Vec<Option<u64>> = quote_previews
+            .iter()
+            .map(|quote_preview| quote_preview.estimated_output)
+            .collect();
+        assert_eq!(estimated_outputs, vec![Some(5_000_000), Some(10_000_000), Some(25_000_000)]);
+    }
+
+    #[tokio::test]
+    async fn leaves_amounts_beyond_a_u64_unquoted() {
+        let swap_provider = FakeSwapProvider::new(&[4; PRESET_AMOUNTS.len()]);
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let quote_previews =
+            get_quote_previews(&swap_provider, &input_mint, &output_mint, 20, 50, None).await;
+
+        assert!(quote_previews
+            .iter()
+            .all(|quote_preview| quote_preview.estimated_output.is_none()));
+        assert!(swap_provider.quoted_route_limits.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+};
+use spl_memo::build_memo;
+use std::str::FromStr;
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+const MAX_MEMO_LENGTH: usize = 128;
+
+// The merchant's reference key, or a fresh one when the link doesn't carry any
+pub fn parse_reference(reference: &Option<String>) -> Result<Pubkey> {
+    match reference {
+        Some(reference) => Pubkey::from_str(reference)
+            .or_else(|_| Err(Error::from(ActionError::InvalidReferencePublicKey))),
+        None => Ok(Keypair::new().pubkey()),
+    }
+}
+
+// Solana Pay style: a read-only, non-signer account that getSignaturesForAddress can find
+pub fn add_reference(instruction: &mut Instruction, reference: &Pubkey) {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*reference, false));
+}
+
+pub fn memo_instruction(memo: &str) -> Result<Instruction> {
+    if memo.is_empty() || memo.len() > MAX_MEMO_LENGTH {
+        return Err(Error::from(ActionError::InvalidMemo));
+    }
+
+    Ok(build_memo(memo.as_bytes(), &[]))
+}
@@ -0,0 +1,121 @@
+use sha2::{Digest, Sha256};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use std::str::FromStr;
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+const NAME_SERVICE_PROGRAM_ID: Pubkey = pubkey!("namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX");
+const SOL_TLD_AUTHORITY: Pubkey = pubkey!("58PwtjSDuFHuUkYjH9BYnnQKHfwo9reZhC2zMJv9JPkx");
+const HASH_PREFIX: &str = "SPL Name Service";
+const SOL_DOMAIN_SUFFIX: &str = ".sol";
+// The name record header is parent, owner and class, 32 bytes each
+const OWNER_OFFSET: usize = 32;
+
+pub fn is_sol_domain(receiver: &str) -> bool {
+    receiver.to_lowercase().ends_with(SOL_DOMAIN_SUFFIX)
+}
+
+// Accepts a base58 pubkey or a .sol domain, which resolves to the domain's owner
+pub async fn resolve_receiver(client: &RpcClient, receiver: &str) -> Result<Pubkey> {
+    if !is_sol_domain(receiver) {
+        return Pubkey::from_str(receiver)
+            .or_else(|_| Err(Error::from(ActionError::InvalidReceiverPublicKey)));
+    }
+
+    let domain = receiver.to_lowercase();
+    let name = &domain[..domain.len() - SOL_DOMAIN_SUFFIX.len()];
+
+    // Only second-level domains such as alice.sol
+    if name.is_empty() || name.contains('.') {
+        return Err(Error::from(ActionError::InvalidDomain));
+    }
+
+    let name_account = get_name_account(name);
+
+    let account = client.get_account(&name_account).await.or_else(|err| {
+        println!("Error obtaining the name account of {}: {}", domain, err);
+        Err(Error::from(ActionError::DomainNotFound))
+    })?;
+
+    let owner = account
+        .data
+        .get(OWNER_OFFSET..OWNER_OFFSET + 32)
+        .and_then(|owner| Pubkey::try_from(owner).ok())
+        .ok_or(Error::from(ActionError::DomainNotFound))?;
+
+    check_domain_owner(&owner).or_else(|err| {
+        println!("Domain {} is owned by {}, which can't receive payments", domain, owner);
+        Err(Error::from(err))
+    })?;
+
+    Ok(owner)
+}
+
+// A tokenized domain is owned by the Name Tokenizer's PDA, whose token accounts the domain's
+// holder can't spend from, so only wallet keys on the ed25519 curve are paid
+fn check_domain_owner(owner: &Pubkey) -> std::result::Result<(), ActionError> {
+    if *owner == Pubkey::default() {
+        return Err(ActionError::DomainNotFound);
+    }
+
+    if !owner.is_on_curve() {
+        return Err(ActionError::DomainOwnerNotAWallet);
+    }
+
+    Ok(())
+}
+
+fn get_name_account(name: &str) -> Pubkey {
+    let hashed_name = Sha256::digest(format!("{}{}", HASH_PREFIX, name).as_bytes());
+
+    Pubkey::find_program_address(
+        &[
+            hashed_name.as_slice(),
+            Pubkey::default().as_ref(),
+            SOL_TLD_AUTHORITY.as_ref(),
+        ],
+        &NAME_SERVICE_PROGRAM_ID,
+    )
+    .0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    #[test]
+    fn derives_the_name_account_of_a_domain() {
+        // bonfida.sol, as derived by Bonfida's SDK
+        assert_eq!(
+            get_name_account("bonfida").to_string(),
+            "Crf8hzfthWGbGbLTVCiqRqV5MVnbpHB1L9KQMd6gsinb"
+        );
+    }
+
+    #[test]
+    fn only_pays_wallet_owners() {
+        // Program derived, like the Name Tokenizer's account holding a tokenized domain
+        let program_address = get_name_account("bonfida");
+
+        assert!(check_domain_owner(&Keypair::new().pubkey()).is_ok());
+        assert!(matches!(
+            check_domain_owner(&program_address),
+            Err(ActionError::DomainOwnerNotAWallet)
+        ));
+        assert!(matches!(
+            check_domain_owner(&Pubkey::default()),
+            Err(ActionError::DomainNotFound)
+        ));
+    }
+
+    #[test]
+    fn detects_sol_domains() {
+        assert!(is_sol_domain("alice.sol"));
+        assert!(is_sol_domain("Alice.SOL"));
+        assert!(!is_sol_domain("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"));
+        assert!(!is_sol_domain("alice.solana"));
+    }
+}
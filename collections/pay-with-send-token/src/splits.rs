@@ -0,0 +1,331 @@
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_instruction};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token::{
+    instruction::{close_account, sync_native},
+    ID as TOKEN_PROGRAM_ID,
+};
+use spl_token_2022::instruction::transfer_checked;
+use std::str::FromStr;
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::swap_provider::SwapMode;
+use crate::token::{is_native_sol, MintAccount};
+
+const MAX_SPLIT_BPS: u16 = 10_000;
+// Each receiver adds an ATA creation and a transfer, more would not fit in a transaction
+const MAX_SPLITS: usize = 5;
+
+pub struct Split {
+    pub receiver: Pubkey,
+    pub bps: u16,
+}
+
+// A transfer of `amount` into one receiver's token account
+pub struct SplitPayment {
+    pub token_account: Pubkey,
+    pub amount: u64,
+}
+
+// Parses `pubkey:bps,pubkey:bps`, the receiver of the route comes first and keeps the rest
+pub fn parse_splits(receiver: &Pubkey, splits: &Option<String>) -> Result<Vec<Split>> {
+    let mut parsed_splits = vec![Split {
+        receiver: *receiver,
+        bps: MAX_SPLIT_BPS,
+    }];
+
+    let splits = match splits {
+        Some(splits) => splits,
+        None => return Ok(parsed_splits),
+    };
+
+    for split in splits.split(',') {
+        let (split_receiver, bps) = split
+            .split_once(':')
+            .ok_or(Error::from(ActionError::InvalidSplits))?;
+        let split_receiver = Pubkey::from_str(split_receiver.trim())
+            .or_else(|_| Err(Error::from(ActionError::InvalidSplits)))?;
+        let bps = bps
+            .trim()
+            .parse::<u16>()
+            .or_else(|_| Err(Error::from(ActionError::InvalidSplits)))?;
+
+        if bps == 0 || parsed_splits.iter().any(|split| split.receiver == split_receiver) {
+            return Err(Error::from(ActionError::InvalidSplits));
+        }
+
+        parsed_splits[0].bps = parsed_splits[0]
+            .bps
+            .checked_sub(bps)
+            .ok_or(Error::from(ActionError::InvalidSplits))?;
+
+        parsed_splits.push(Split {
+            receiver: split_receiver,
+            bps,
+        });
+    }
+
+    if parsed_splits.len() > MAX_SPLITS {
+        return Err(Error::from(ActionError::InvalidSplits));
+    }
+
+    // The route receiver may give away its whole share
+    parsed_splits.retain(|split| split.bps > 0);
+
+    Ok(parsed_splits)
+}
+
+// ExactIn only guarantees the swap's minimum output, splitting that would leave any surplus
+// with the payer, so a swapped payment can only be split by exact amounts
+pub fn check_split_mode(splits: &[Split], swap_mode: SwapMode, needs_swap: bool) -> Result<()> {
+    if splits.len() > 1 && needs_swap && swap_mode == SwapMode::ExactIn {
+        return Err(Error::from(ActionError::SplitsRequireExactOut));
+    }
+
+    Ok(())
+}
+
+// Every hop of a Token-2022 transfer fee mint withholds a fee, so neither the swap into the payer's
+// account nor the fan-out from it would leave each receiver its exact share
+pub fn check_split_mint(splits: &[Split], mint_account: &MintAccount) -> Result<()> {
+    if splits.len() > 1 && mint_account.has_transfer_fee() {
+        return Err(Error::from(ActionError::SplitsUnsupportedForTransferFee));
+    }
+
+    Ok(())
+}
+
+pub fn create_token_account_instructions(
+    payer: &Pubkey,
+    splits: &[Split],
+    mint: &Pubkey,
+    mint_account: &MintAccount,
+) -> Vec<Instruction> {
+    splits
+        .iter()
+        .map(|split| {
+            create_associated_token_account_idempotent(
+                payer,
+                &split.receiver,
+                mint,
+                &mint_account.token_program_id,
+            )
+        })
+        .collect()
+}
+
+// Divides `amount` by weight, rounding dust goes to the first receiver
+pub fn split_payments(
+    splits: &[Split],
+    amount: u64,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Vec<SplitPayment> {
+    let mut payments: Vec<SplitPayment> = splits
+        .iter()
+        .map(|split| SplitPayment {
+            token_account: get_associated_token_address_with_program_id(
+                &split.receiver,
+                mint,
+                token_program_id,
+            ),
+            amount: (amount as u128 * split.bps as u128 / MAX_SPLIT_BPS as u128) as u64,
+        })
+        .collect();
+
+    let distributed_amount: u64 = payments.iter().map(|payment| payment.amount).sum();
+
+    if let Some(payment) = payments.first_mut() {
+        payment.amount += amount - distributed_amount;
+    }
+
+    payments
+}
+
+// Wraps the payer's SOL straight into each receiver's wSOL account
+pub fn native_transfer_instructions(
+    payer: &Pubkey,
+    payments: &[SplitPayment],
+) -> Result<Vec<Instruction>> {
+    let mut instructions = vec![];
+
+    for payment in payments {
+        instructions.push(system_instruction::transfer(
+            payer,
+            &payment.token_account,
+            payment.amount,
+        ));
+        instructions.push(
+            sync_native(&TOKEN_PROGRAM_ID, &payment.token_account)
+                .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?,
+        );
+    }
+
+    Ok(instructions)
+}
+
+// The swap's wSOL lands in the payer's account before the fan-out, close it once emptied
+pub fn close_native_account_instruction(
+    payer: &Pubkey,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Option<Instruction>> {
+    if !is_native_sol(mint) {
+        return Ok(None);
+    }
+
+    close_account(&TOKEN_PROGRAM_ID, token_account, payer, payer, &[])
+        .map(Some)
+        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))
+}
+
+pub fn token_transfer_instructions(
+    source: &Pubkey,
+    authority: &Pubkey,
+    mint: &Pubkey,
+    mint_account: &MintAccount,
+    payments: &[SplitPayment],
+) -> Result<Vec<Instruction>> {
+    payments
+        .iter()
+        .map(|payment| {
+            transfer_checked(
+                &mint_account.token_program_id,
+                source,
+                mint,
+                &payment.token_account,
+                authority,
+                &[],
+                payment.amount,
+                mint_account.decimals,
+            )
+            .or_else(|_| Err(Error::from(ActionError::InternalServerError)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receivers() -> (Pubkey, Pubkey, Pubkey) {
+        (
+            Pubkey::new_from_array([1; 32]),
+            Pubkey::new_from_array([2; 32]),
+            Pubkey::new_from_array([3; 32]),
+        )
+    }
+
+    fn splits_query(splits: &[(&Pubkey, u16)]) -> Option<String> {
+        Some(
+            splits
+                .iter()
+                .map(|(receiver, bps)| format!("{}:{}", receiver, bps))
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+    }
+
+    #[test]
+    fn pays_the_receiver_alone_without_splits() {
+        let (receiver, _, _) = receivers();
+        let splits = parse_splits(&receiver, &None).ok().unwrap();
+
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].receiver, receiver);
+        assert_eq!(splits[0].bps, 10_000);
+    }
+
+    #[test]
+    fn leaves_the_rest_to_the_receiver() {
+        let (receiver, second, third) = receivers();
+        let query = splits_query(&[(&second, 2_500), (&third, 1_000)]);
+        let splits = parse_splits(&receiver, &query).ok().unwrap();
+
+        let shares: Vec<(Pubkey, u16)> =
+            splits.iter().map(|split| (split.receiver, split.bps)).collect();
+
+        assert_eq!(shares, vec![(receiver, 6_500), (second, 2_500), (third, 1_000)]);
+    }
+
+    #[test]
+    fn drops_a_receiver_that_gives_everything_away() {
+        let (receiver, second, _) = receivers();
+        let splits = parse_splits(&receiver, &splits_query(&[(&second, 10_000)])).ok().unwrap();
+
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].receiver, second);
+    }
+
+    #[test]
+    fn rejects_invalid_splits() {
+        let (receiver, second, third) = receivers();
+
+        for query in [
+            splits_query(&[(&second, 0)]),
+            splits_query(&[(&second, 6_000), (&third, 5_000)]),
+            splits_query(&[(&second, 100), (&second, 100)]),
+            splits_query(&[(&receiver, 100)]),
+            Some(format!("{}", second)),
+            Some("not-a-pubkey:100".to_string()),
+            Some(format!("{}:-5", second)),
+        ] {
+            assert!(parse_splits(&receiver, &query).is_err(), "{:?} should be invalid", query);
+        }
+
+        let extra_receivers: Vec<Pubkey> =
+            (10..15).map(|byte| Pubkey::new_from_array([byte; 32])).collect();
+        let too_many: Vec<(&Pubkey, u16)> =
+            extra_receivers.iter().map(|receiver| (receiver, 100)).collect();
+
+        assert!(parse_splits(&receiver, &splits_query(&too_many)).is_err());
+    }
+
+    #[test]
+    fn gives_the_rounding_dust_to_the_receiver() {
+        let (receiver, second, third) = receivers();
+        let query = splits_query(&[(&second, 3_333), (&third, 3_333)]);
+        let splits = parse_splits(&receiver, &query).ok().unwrap();
+
+        let payments = split_payments(&splits, 100, &spl_token::native_mint::ID, &TOKEN_PROGRAM_ID);
+        let amounts: Vec<u64> = payments.iter().map(|payment| payment.amount).collect();
+
+        assert_eq!(amounts, vec![34, 33, 33]);
+        assert_eq!(
+            payments[1].token_account,
+            get_associated_token_address_with_program_id(
+                &second,
+                &spl_token::native_mint::ID,
+                &TOKEN_PROGRAM_ID
+            )
+        );
+    }
+
+    #[test]
+    fn requires_exact_out_to_split_a_swap() {
+        let (receiver, second, _) = receivers();
+        let single = parse_splits(&receiver, &None).ok().unwrap();
+        let splits = parse_splits(&receiver, &splits_query(&[(&second, 5_000)])).ok().unwrap();
+
+        assert!(check_split_mode(&splits, SwapMode::ExactIn, true).is_err());
+        assert!(check_split_mode(&splits, SwapMode::ExactOut, true).is_ok());
+        assert!(check_split_mode(&splits, SwapMode::ExactIn, false).is_ok());
+        assert!(check_split_mode(&single, SwapMode::ExactIn, true).is_ok());
+    }
+
+    #[test]
+    fn refuses_to_split_a_transfer_fee_mint() {
+        let (receiver, second, _) = receivers();
+        let single = parse_splits(&receiver, &None).ok().unwrap();
+        let splits = parse_splits(&receiver, &splits_query(&[(&second, 5_000)])).ok().unwrap();
+        let fee_mint_account = MintAccount::with_transfer_fee(6, 100);
+        let plain_mint_account = MintAccount::without_transfer_fee(TOKEN_PROGRAM_ID, 6);
+
+        assert!(check_split_mint(&splits, &fee_mint_account).is_err());
+        assert!(check_split_mint(&single, &fee_mint_account).is_ok());
+        assert!(check_split_mint(&splits, &plain_mint_account).is_ok());
+    }
+}
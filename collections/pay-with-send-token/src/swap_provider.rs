@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::field_instruction;
+use crate::field_pubkey;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwapMode {
+    // The amount is what the payer spends
+    ExactIn,
+    // The amount is what the receiver gets
+    ExactOut,
+}
+
+impl SwapMode {
+    pub fn from_query(mode: &Option<String>) -> Result<SwapMode> {
+        match mode.as_deref() {
+            None | Some("exact_in") => Ok(SwapMode::ExactIn),
+            Some("exact_out") => Ok(SwapMode::ExactOut),
+            Some(_) => Err(Error::from(ActionError::InvalidSwapMode)),
+        }
+    }
+
+    pub fn as_query(&self) -> &'static str {
+        match self {
+            SwapMode::ExactIn => "exact_in",
+            SwapMode::ExactOut => "exact_out",
+        }
+    }
+}
+
+// Limits on the route size, tightened when the swap doesn't fit in a transaction
+#[derive(Clone, Copy, Debug)]
+pub struct RouteLimits {
+    pub max_accounts: u8,
+    pub only_direct_routes: bool,
+}
+
+pub const ROUTE_LIMITS: [RouteLimits; 4] = [
+    RouteLimits {
+        max_accounts: 18,
+        only_direct_routes: false,
+    },
+    RouteLimits {
+        max_accounts: 14,
+        only_direct_routes: false,
+    },
+    RouteLimits {
+        max_accounts: 10,
+        only_direct_routes: false,
+    },
+    RouteLimits {
+        max_accounts: 10,
+        only_direct_routes: true,
+    },
+];
+
+pub struct QuoteRequest {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub swap_mode: SwapMode,
+    pub slippage_bps: u16,
+    pub platform_fee_bps: Option<u16>,
+    pub route_limits: RouteLimits,
+}
+
+#[derive(Clone, Debug)]
+pub struct Quote {
+    pub out_amount: u64,
+    // Maximum input for ExactOut, minimum output for ExactIn
+    pub other_amount_threshold: u64,
+    pub price_impact_pct: f64,
+    // The provider's quote exactly as received, handed back when requesting the swap instructions
+    pub raw: Value,
+}
+
+impl Quote {
+    pub fn check_price_impact(&self, max_price_impact_pct: f64) -> Result<()> {
+        if self.price_impact_pct > max_price_impact_pct {
+            println!(
+                "Price impact {}% exceeds the {}% limit",
+                self.price_impact_pct, max_price_impact_pct
+            );
+            return Err(Error::from(ActionError::PriceImpactTooHigh));
+        }
+
+        Ok(())
+    }
+}
+
+// A missing route is told apart, since a payment re-quoted with tighter limits may have none
+pub enum QuoteError {
+    NoRoute,
+    Other(Error),
+}
+
+impl From<QuoteError> for Error {
+    fn from(error: QuoteError) -> Self {
+        match error {
+            QuoteError::NoRoute => Error::from(ActionError::NoRouteFound),
+            QuoteError::Other(error) => error,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapInstructions {
+    #[serde(with = "field_instruction::option_instruction")]
+    pub token_ledger_instruction: Option<Instruction>,
+    #[serde(with = "field_instruction::vec_instruction")]
+    pub compute_budget_instructions: Vec<Instruction>,
+    #[serde(with = "field_instruction::vec_instruction")]
+    pub setup_instructions: Vec<Instruction>,
+    #[serde(with = "field_instruction::instruction")]
+    pub swap_instruction: Instruction,
+    #[serde(with = "field_instruction::option_instruction")]
+    pub cleanup_instruction: Option<Instruction>,
+    #[serde(with = "field_pubkey::vec")]
+    pub address_lookup_table_addresses: Vec<Pubkey>,
+    pub prioritization_fee_lamports: u64,
+}
+
+impl SwapInstructions {
+    // Flattens the swap into the order the instructions have to be executed
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        let mut instructions = vec![];
+
+        if let Some(instruction) = self.token_ledger_instruction {
+            instructions.push(instruction);
+        }
+
+        instructions.extend(self.compute_budget_instructions);
+        instructions.extend(self.setup_instructions);
+        instructions.push(self.swap_instruction);
+
+        if let Some(instruction) = self.cleanup_instruction {
+            instructions.push(instruction);
+        }
+
+        instructions
+    }
+}
+
+// An aggregator able to quote a swap and build its instructions, held by PayConfig
+#[async_trait]
+pub trait SwapProvider: Send + Sync {
+    async fn quote(&self, request: &QuoteRequest) -> std::result::Result<Quote, QuoteError>;
+
+    async fn swap_instructions(
+        &self,
+        account_pubkey: &Pubkey,
+        destination_token_account: &Pubkey,
+        fee_token_account: Option<Pubkey>,
+        quote: Quote,
+    ) -> Result<SwapInstructions>;
+}
@@ -0,0 +1,141 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token::native_mint;
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    state::Mint,
+};
+use std::str::FromStr;
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+pub const NATIVE_SOL_ALIAS: &str = "SOL";
+pub const NATIVE_SOL_DECIMALS: u8 = native_mint::DECIMALS;
+
+// Parses a token mint param, accepting the SOL alias for wrapped SOL
+pub fn parse_token_mint(token_mint: &str) -> Result<Pubkey> {
+    if token_mint.eq_ignore_ascii_case(NATIVE_SOL_ALIAS) {
+        return Ok(native_mint::ID);
+    }
+
+    Pubkey::from_str(token_mint).or_else(|_| Err(Error::from(ActionError::InvalidTokenMintPublicKey)))
+}
+
+pub fn is_native_sol(mint: &Pubkey) -> bool {
+    *mint == native_mint::ID
+}
+
+pub struct MintAccount {
+    pub token_program_id: Pubkey,
+    pub decimals: u8,
+    transfer_fee_config: Option<TransferFeeConfig>,
+    epoch: u64,
+}
+
+impl MintAccount {
+    // Amount to send so that `amount` arrives after the Token-2022 transfer fee is withheld
+    pub fn amount_before_fee(&self, amount: u64) -> u64 {
+        match &self.transfer_fee_config {
+            Some(transfer_fee_config) => transfer_fee_config
+                .get_epoch_fee(self.epoch)
+                .calculate_pre_fee_amount(amount)
+                .unwrap_or(amount),
+            None => amount,
+        }
+    }
+
+    pub fn has_transfer_fee(&self) -> bool {
+        self.transfer_fee_config.is_some()
+    }
+
+    #[cfg(test)]
+    pub fn without_transfer_fee(token_program_id: Pubkey, decimals: u8) -> MintAccount {
+        MintAccount {
+            token_program_id,
+            decimals,
+            transfer_fee_config: None,
+            epoch: 0,
+        }
+    }
+
+    // A Token-2022 mint withholding `fee_bps` of every transfer
+    #[cfg(test)]
+    pub fn with_transfer_fee(decimals: u8, fee_bps: u16) -> MintAccount {
+        let mut transfer_fee_config = TransferFeeConfig::default();
+        transfer_fee_config.newer_transfer_fee.transfer_fee_basis_points = fee_bps.into();
+        transfer_fee_config.newer_transfer_fee.maximum_fee = u64::MAX.into();
+
+        MintAccount {
+            token_program_id: spl_token_2022::ID,
+            decimals,
+            transfer_fee_config: Some(transfer_fee_config),
+            epoch: 0,
+        }
+    }
+}
+
+// Reads the mint's owning program and decimals, and its transfer fee when it is a Token-2022 mint
+pub async fn get_mint_account(client: &RpcClient, mint: &Pubkey) -> Result<MintAccount> {
+    if is_native_sol(mint) {
+        return Ok(MintAccount {
+            token_program_id: spl_token::ID,
+            decimals: NATIVE_SOL_DECIMALS,
+            transfer_fee_config: None,
+            epoch: 0,
+        });
+    }
+
+    let account = client
+        .get_account(mint)
+        .await
+        .or_else(|_| Err(Error::from(ActionError::ErrorObtainingTokenAccountData)))?;
+
+    if account.owner != spl_token::ID && account.owner != spl_token_2022::ID {
+        return Err(Error::from(ActionError::UnsupportedTokenProgram));
+    }
+
+    // Token-2022 mints share the base layout with Token mints
+    let mint_state = StateWithExtensions::<Mint>::unpack(&account.data)
+        .or_else(|_| Err(Error::from(ActionError::TokenDecimalsUnavailable)))?;
+    let transfer_fee_config = mint_state.get_extension::<TransferFeeConfig>().ok().copied();
+
+    let epoch = match transfer_fee_config {
+        Some(_) => {
+            client
+                .get_epoch_info()
+                .await
+                .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?
+                .epoch
+        }
+        None => 0,
+    };
+
+    Ok(MintAccount {
+        token_program_id: account.owner,
+        decimals: mint_state.base.decimals,
+        transfer_fee_config,
+        epoch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grosses_up_the_amount_for_the_transfer_fee() {
+        let mint_account = MintAccount::with_transfer_fee(6, 100);
+
+        assert!(mint_account.has_transfer_fee());
+        assert_eq!(mint_account.amount_before_fee(9_900_000), 10_000_000);
+    }
+
+    #[test]
+    fn leaves_the_amount_without_a_transfer_fee() {
+        let mint_account = MintAccount::without_transfer_fee(spl_token::ID, 6);
+
+        assert!(!mint_account.has_transfer_fee());
+        assert_eq!(mint_account.amount_before_fee(9_900_000), 9_900_000);
+    }
+}
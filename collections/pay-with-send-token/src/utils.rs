@@ -1,4 +1,16 @@
-pub fn format_pubkey(text: &String, length: usize) -> String {
+use solana_sdk::{
+    compute_budget::{self, ComputeBudgetInstruction},
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    transaction::Transaction,
+};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+pub fn format_pubkey(text: &str, length: usize) -> String {
     if text.len() <= length {
         return text.to_string();
     }
@@ -11,3 +23,89 @@ pub fn format_pubkey(text: &String, length: usize) -> String {
 
     format!("{}{}{}", start, ellipsis, end)
 }
+
+// Measures the transaction finalize_transaction will build, before paying for the compute budget
+// and blockhash RPCs: set_compute_budget adds at most these two instructions, and the default
+// blockhash serializes to the same size as a real one
+pub fn fits_in_packet(instructions: &[Instruction], payer: &Pubkey) -> Result<bool> {
+    let mut measured_instructions = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(0),
+        ComputeBudgetInstruction::set_compute_unit_price(0),
+    ];
+    measured_instructions.extend(
+        instructions
+            .iter()
+            .filter(|instruction| instruction.program_id != compute_budget::id())
+            .cloned(),
+    );
+
+    let transaction = Transaction::new_unsigned(Message::new(&measured_instructions, Some(payer)));
+    let size = bincode::serialized_size(&transaction)
+        .or_else(|_| Err(Error::from(ActionError::InternalServerError)))?;
+
+    Ok(size as usize <= PACKET_DATA_SIZE)
+}
+
+// Formats an amount in base units from its digits, so no decimals count can overflow
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0width$}", amount, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        return whole.to_string();
+    }
+
+    format!("{}.{}", whole, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_amounts_without_trailing_zeros() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(1_000_000, 6), "1");
+        assert_eq!(format_amount(1, 9), "0.000000001");
+        assert_eq!(format_amount(0, 6), "0");
+        assert_eq!(format_amount(42, 0), "42");
+    }
+
+    #[test]
+    fn formats_the_largest_amount() {
+        assert_eq!(format_amount(u64::MAX, 9), "18446744073.709551615");
+    }
+
+    #[test]
+    fn formats_mints_with_more_decimals_than_a_u64_holds() {
+        assert_eq!(format_amount(1, 20), "0.00000000000000000001");
+        assert_eq!(
+            format_amount(u64::MAX, 255),
+            format!("0.{}18446744073709551615", "0".repeat(235))
+        );
+        assert_eq!(format_amount(0, 255), "0");
+    }
+
+    #[test]
+    fn measures_the_transaction_with_its_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let transfer =
+            |_| solana_sdk::system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+
+        let small: Vec<Instruction> = (0..3).map(transfer).collect();
+        let large: Vec<Instruction> = (0..30).map(transfer).collect();
+
+        assert!(fits_in_packet(&small, &payer).ok().unwrap());
+        assert!(!fits_in_packet(&large, &payer).ok().unwrap());
+    }
+
+    #[test]
+    fn shortens_long_keys_around_an_ellipsis() {
+        let key = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+        assert_eq!(format_pubkey(key, 8), "EPjF...Dt1v");
+        assert_eq!(format_pubkey("short", 8), "short");
+    }
+}
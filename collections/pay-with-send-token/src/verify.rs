@@ -0,0 +1,257 @@
+use serde::Serialize;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use std::str::FromStr;
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+const REFERENCE_SIGNATURES_LIMIT: usize = 10;
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Received,
+    NotReceived,
+    // Less than the expected amount reached the receiver
+    Underpaid,
+    // The transaction doesn't carry the order's reference, so it pays for something else
+    ReferenceMismatch,
+    Failed,
+}
+
+// What a point-of-sale terminal expects for an order
+pub struct ExpectedPayment {
+    pub receiver: Pubkey,
+    pub mint: Pubkey,
+    pub reference: Option<Pubkey>,
+    // In base units of the mint
+    pub amount: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentVerdict {
+    pub status: PaymentStatus,
+    pub signature: String,
+    pub payer: Option<String>,
+    pub mint: String,
+    pub amount_received: u64,
+    pub expected_amount: Option<u64>,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+}
+
+pub fn parse_signature(signature: &str) -> Result<Signature> {
+    Signature::from_str(signature).or_else(|_| Err(Error::from(ActionError::InvalidSignature)))
+}
+
+// Latest successful transaction mentioning the reference, or the latest one if all of them failed
+pub async fn find_reference_signature(client: &RpcClient, reference: &Pubkey) -> Result<Signature> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        limit: Some(REFERENCE_SIGNATURES_LIMIT),
+        commitment: Some(CommitmentConfig::confirmed()),
+        ..GetConfirmedSignaturesForAddress2Config::default()
+    };
+
+    let signatures = client
+        .get_signatures_for_address_with_config(reference, config)
+        .await
+        .or_else(|err| {
+            println!("Error obtaining signatures for reference {}: {}", reference, err);
+            Err(Error::from(ActionError::ErrorObtainingTransaction))
+        })?;
+
+    let signature = signatures
+        .iter()
+        .find(|signature| signature.err.is_none())
+        .or(signatures.first())
+        .ok_or(Error::from(ActionError::PaymentNotFound))?;
+
+    parse_signature(&signature.signature)
+}
+
+pub async fn verify_payment(
+    client: &RpcClient,
+    signature: &Signature,
+    expected: &ExpectedPayment,
+) -> Result<PaymentVerdict> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+
+    let confirmed_transaction = client
+        .get_transaction_with_config(signature, config)
+        .await
+        .or_else(|err| {
+            println!("Error obtaining transaction {}: {}", signature, err);
+            Err(Error::from(ActionError::PaymentNotFound))
+        })?;
+
+    let meta = confirmed_transaction
+        .transaction
+        .meta
+        .ok_or(Error::from(ActionError::ErrorObtainingTransaction))?;
+
+    let mut account_keys: Vec<String> = confirmed_transaction
+        .transaction
+        .transaction
+        .decode()
+        .map(|transaction| {
+            transaction
+                .message
+                .static_account_keys()
+                .iter()
+                .map(|key| key.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // The fee payer is always the first account of the message
+    let payer = account_keys.first().cloned();
+
+    if let OptionSerializer::Some(loaded_addresses) = &meta.loaded_addresses {
+        account_keys.extend(loaded_addresses.writable.iter().cloned());
+        account_keys.extend(loaded_addresses.readonly.iter().cloned());
+    }
+
+    let reference_found = match &expected.reference {
+        Some(reference) => account_keys.contains(&reference.to_string()),
+        None => true,
+    };
+
+    let pre_balance =
+        receiver_balance(meta.pre_token_balances.into(), &expected.receiver, &expected.mint);
+    let post_balance =
+        receiver_balance(meta.post_token_balances.into(), &expected.receiver, &expected.mint);
+    let amount_received = post_balance.saturating_sub(pre_balance);
+
+    Ok(PaymentVerdict {
+        status: payment_status(
+            meta.err.is_some(),
+            reference_found,
+            amount_received,
+            expected.amount,
+        ),
+        signature: signature.to_string(),
+        payer,
+        mint: expected.mint.to_string(),
+        amount_received,
+        expected_amount: expected.amount,
+        slot: confirmed_transaction.slot,
+        block_time: confirmed_transaction.block_time,
+    })
+}
+
+fn payment_status(
+    failed: bool,
+    reference_found: bool,
+    amount_received: u64,
+    expected_amount: Option<u64>,
+) -> PaymentStatus {
+    if failed {
+        return PaymentStatus::Failed;
+    }
+
+    if !reference_found {
+        return PaymentStatus::ReferenceMismatch;
+    }
+
+    match expected_amount {
+        _ if amount_received == 0 => PaymentStatus::NotReceived,
+        Some(expected_amount) if amount_received < expected_amount => PaymentStatus::Underpaid,
+        _ => PaymentStatus::Received,
+    }
+}
+
+// Sum of the receiver's token accounts for the mint, in base units
+fn receiver_balance(
+    token_balances: Option<Vec<UiTransactionTokenBalance>>,
+    receiver: &Pubkey,
+    mint: &Pubkey,
+) -> u64 {
+    let receiver = receiver.to_string();
+    let mint = mint.to_string();
+
+    token_balances
+        .unwrap_or_default()
+        .iter()
+        .filter(|balance| {
+            balance.mint == mint
+                && matches!(&balance.owner, OptionSerializer::Some(owner) if *owner == receiver)
+        })
+        .filter_map(|balance| balance.ui_token_amount.amount.parse::<u64>().ok())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As returned in a transaction's pre and post token balances
+    fn token_balance(owner: &Pubkey, mint: &Pubkey, amount: u64) -> UiTransactionTokenBalance {
+        serde_json::from_value(serde_json::json!({
+            "accountIndex": 1,
+            "mint": mint.to_string(),
+            "uiTokenAmount": {
+                "uiAmount": null,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": "0"
+            },
+            "owner": owner.to_string(),
+            "programId": spl_token::ID.to_string()
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn sums_only_the_receivers_accounts_for_the_mint() {
+        let receiver = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let balances = vec![
+            token_balance(&receiver, &mint, 1_000),
+            token_balance(&receiver, &mint, 500),
+            token_balance(&receiver, &Pubkey::new_unique(), 7_000),
+            token_balance(&Pubkey::new_unique(), &mint, 9_000),
+        ];
+
+        assert_eq!(receiver_balance(Some(balances), &receiver, &mint), 1_500);
+        assert_eq!(receiver_balance(None, &receiver, &mint), 0);
+    }
+
+    #[test]
+    fn failed_transactions_never_count() {
+        assert_eq!(payment_status(true, true, 100, None), PaymentStatus::Failed);
+    }
+
+    #[test]
+    fn requires_the_reference() {
+        assert_eq!(
+            payment_status(false, false, 100, Some(100)),
+            PaymentStatus::ReferenceMismatch
+        );
+    }
+
+    #[test]
+    fn requires_the_expected_amount() {
+        assert_eq!(payment_status(false, true, 0, Some(100)), PaymentStatus::NotReceived);
+        assert_eq!(payment_status(false, true, 1, Some(100)), PaymentStatus::Underpaid);
+        assert_eq!(payment_status(false, true, 100, Some(100)), PaymentStatus::Received);
+        assert_eq!(payment_status(false, true, 101, Some(100)), PaymentStatus::Received);
+    }
+
+    #[test]
+    fn accepts_any_amount_without_an_expectation() {
+        assert_eq!(payment_status(false, true, 0, None), PaymentStatus::NotReceived);
+        assert_eq!(payment_status(false, true, 1, None), PaymentStatus::Received);
+    }
+}
@@ -0,0 +1,118 @@
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+// A positive decimal amount parsed exactly from a query string, without going through floats
+pub struct DecimalAmount {
+    integer: String,
+    fraction: String,
+}
+
+impl DecimalAmount {
+    pub fn parse(amount: &str) -> Result<DecimalAmount> {
+        DecimalAmount::try_parse(amount).or_else(|err| Err(Error::from(err)))
+    }
+
+    // Converts the amount into base units of a token with the given decimals
+    pub fn to_base_units(&self, decimals: u8) -> Result<u64> {
+        self.try_to_base_units(decimals)
+            .or_else(|err| Err(Error::from(err)))
+    }
+
+    fn try_parse(amount: &str) -> std::result::Result<DecimalAmount, ActionError> {
+        let amount = amount.trim();
+
+        if amount.starts_with('-') {
+            return Err(ActionError::NegativeAmount);
+        }
+
+        let (integer, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(ActionError::InvalidAmount);
+        }
+
+        let integer = integer.trim_start_matches('0');
+        let fraction = fraction.trim_end_matches('0');
+
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(ActionError::ZeroAmount);
+        }
+
+        Ok(DecimalAmount {
+            integer: integer.to_string(),
+            fraction: fraction.to_string(),
+        })
+    }
+
+    fn try_to_base_units(&self, decimals: u8) -> std::result::Result<u64, ActionError> {
+        let decimals = decimals as usize;
+
+        if self.fraction.len() > decimals {
+            return Err(ActionError::AmountTooPrecise);
+        }
+
+        let digits = format!("{}{:0<decimals$}", self.integer, self.fraction);
+
+        digits.parse::<u64>().or(Err(ActionError::AmountTooLarge))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_units(amount: &str, decimals: u8) -> std::result::Result<u64, ActionError> {
+        DecimalAmount::try_parse(amount)?.try_to_base_units(decimals)
+    }
+
+    #[test]
+    fn converts_exactly_to_base_units() {
+        assert!(matches!(base_units("1.5", 6), Ok(1_500_000)));
+        assert!(matches!(base_units("0.1", 9), Ok(100_000_000)));
+        assert!(matches!(base_units(" 007.250 ", 2), Ok(725)));
+        assert!(matches!(base_units(".5", 1), Ok(5)));
+        assert!(matches!(base_units("12", 0), Ok(12)));
+    }
+
+    #[test]
+    fn rejects_negative_amounts() {
+        assert!(matches!(base_units("-1", 6), Err(ActionError::NegativeAmount)));
+        assert!(matches!(base_units("-0.5", 6), Err(ActionError::NegativeAmount)));
+    }
+
+    #[test]
+    fn rejects_zero() {
+        assert!(matches!(base_units("0", 6), Err(ActionError::ZeroAmount)));
+        assert!(matches!(base_units("0.000", 6), Err(ActionError::ZeroAmount)));
+    }
+
+    #[test]
+    fn rejects_non_numeric_amounts() {
+        for amount in ["NaN", "inf", "1e5", "1.2.3", "", ".", "1,5", "+1"] {
+            assert!(
+                matches!(base_units(amount, 6), Err(ActionError::InvalidAmount)),
+                "{} should be invalid",
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_more_decimals_than_the_token_has() {
+        assert!(matches!(base_units("1.0000001", 6), Err(ActionError::AmountTooPrecise)));
+        assert!(matches!(base_units("0.5", 0), Err(ActionError::AmountTooPrecise)));
+        // Trailing zeros carry no precision
+        assert!(matches!(base_units("1.5000000", 6), Ok(1_500_000)));
+    }
+
+    #[test]
+    fn rejects_amounts_over_u64() {
+        assert!(matches!(base_units("18446744073709551615", 0), Ok(u64::MAX)));
+        assert!(matches!(base_units("18446744073709551616", 0), Err(ActionError::AmountTooLarge)));
+        assert!(matches!(base_units("18446744073709.551616", 6), Err(ActionError::AmountTooLarge)));
+    }
+}
@@ -0,0 +1,119 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::{self, ComputeBudgetInstruction},
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    transaction::Transaction,
+};
+
+use crate::simulation::simulate;
+
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+// Headroom over the simulated units, accounts can change before the transaction lands
+const COMPUTE_UNIT_MARGIN_PCT: u64 = 10;
+// getRecentPrioritizationFees accepts at most 128 accounts
+const MAX_PRIORITIZATION_FEE_ACCOUNTS: usize = 128;
+const PRIORITIZATION_FEE_PERCENTILE: usize = 75;
+const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
+
+// Replaces any compute budget instructions with a unit limit from a simulation and a price from
+// recent prioritization fees, keeping the total priority fee under `max_priority_fee_lamports`
+pub async fn set_compute_budget(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    rpc_url: &str,
+    max_priority_fee_lamports: u64,
+) -> Vec<Instruction> {
+    let client = RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+
+    let instructions: Vec<Instruction> = instructions
+        .iter()
+        .filter(|instruction| instruction.program_id != compute_budget::id())
+        .cloned()
+        .collect();
+
+    let compute_unit_limit = get_compute_unit_limit(&client, &instructions, payer).await;
+    let compute_unit_price = get_compute_unit_price(&client, &instructions, payer)
+        .await
+        .min(
+            max_priority_fee_lamports.saturating_mul(MICRO_LAMPORTS_PER_LAMPORT)
+                / compute_unit_limit as u64,
+        );
+
+    let mut compute_budget_instructions =
+        vec![ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit)];
+
+    if compute_unit_price > 0 {
+        compute_budget_instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+            compute_unit_price,
+        ));
+    }
+
+    compute_budget_instructions.extend(instructions);
+    compute_budget_instructions
+}
+
+// Falls back to the maximum limit when the simulation doesn't report the units consumed
+async fn get_compute_unit_limit(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+) -> u32 {
+    let mut simulated_instructions =
+        vec![ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT)];
+    simulated_instructions.extend_from_slice(instructions);
+
+    let transaction = Transaction::new_unsigned(Message::new(&simulated_instructions, Some(payer)));
+
+    let units_consumed = match simulate(client, &transaction).await {
+        Ok(result) if result.err.is_none() => result.units_consumed,
+        Ok(result) => {
+            println!("Compute unit simulation failed: {:?}", result.err);
+            None
+        }
+        Err(err) => {
+            println!("Error simulating compute units: {}", err);
+            None
+        }
+    };
+
+    match units_consumed {
+        Some(units_consumed) => (units_consumed + units_consumed * COMPUTE_UNIT_MARGIN_PCT / 100)
+            .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32,
+        None => MAX_COMPUTE_UNIT_LIMIT,
+    }
+}
+
+// A high percentile of the recent fees paid to write the same accounts, in micro-lamports per unit
+async fn get_compute_unit_price(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+) -> u64 {
+    let mut writable_accounts = vec![*payer];
+
+    for account in instructions.iter().flat_map(|instruction| &instruction.accounts) {
+        if account.is_writable && !writable_accounts.contains(&account.pubkey) {
+            writable_accounts.push(account.pubkey);
+        }
+    }
+
+    writable_accounts.truncate(MAX_PRIORITIZATION_FEE_ACCOUNTS);
+
+    let mut fees: Vec<u64> = match client.get_recent_prioritization_fees(&writable_accounts).await {
+        Ok(fees) => fees.iter().map(|fee| fee.prioritization_fee).collect(),
+        Err(err) => {
+            println!("Error obtaining recent prioritization fees: {}", err);
+            return 0;
+        }
+    };
+
+    if fees.is_empty() {
+        return 0;
+    }
+
+    fees.sort_unstable();
+    fees[(fees.len() - 1) * PRIORITIZATION_FEE_PERCENTILE / 100]
+}
@@ -0,0 +1,29 @@
+use znap::prelude::*;
+
+#[derive(ErrorCode)]
+pub enum ActionError {
+    #[error(msg = "Error obtaining a recent blockhash")]
+    ErrorObtainingRecentBlockhash,
+    #[error(msg = "Invalid amount, use a decimal number such as 1.5")]
+    InvalidAmount,
+    #[error(msg = "Amount cannot be negative")]
+    NegativeAmount,
+    #[error(msg = "Amount must be greater than zero")]
+    ZeroAmount,
+    #[error(msg = "Amount has more decimal places than the token supports")]
+    AmountTooPrecise,
+    #[error(msg = "Amount is too large")]
+    AmountTooLarge,
+    #[error(msg = "Insufficient funds to complete this transaction")]
+    InsufficientFunds,
+    #[error(msg = "The price moved beyond the slippage tolerance, try again")]
+    SlippageExceeded,
+    #[error(msg = "An account this transaction creates is already in use")]
+    AccountAlreadyInUse,
+    #[error(msg = "The transaction failed in simulation")]
+    SimulationFailed,
+    #[error(msg = "Invalid program allowlist")]
+    InvalidProgramAllowlist,
+    #[error(msg = "The transaction was refused by the transaction policy")]
+    TransactionPolicyViolation,
+}
@@ -0,0 +1,7 @@
+pub mod amount;
+pub mod compute_budget;
+pub mod errors;
+pub mod policy;
+pub mod programs;
+pub mod simulation;
+pub mod transaction;
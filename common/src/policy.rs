@@ -0,0 +1,238 @@
+use solana_sdk::{compute_budget, pubkey::Pubkey, transaction::Transaction};
+use std::{env, str::FromStr};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::programs::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+// Token instructions that hand control of the user's tokens to someone else, with the
+// position of the user's account among the instruction accounts. Shared by Token and Token-2022
+const APPROVE_INSTRUCTION: u8 = 4;
+const APPROVE_OWNER_INDEX: usize = 2;
+const SET_AUTHORITY_INSTRUCTION: u8 = 6;
+const SET_AUTHORITY_CURRENT_AUTHORITY_INDEX: usize = 1;
+const APPROVE_CHECKED_INSTRUCTION: u8 = 13;
+const APPROVE_CHECKED_OWNER_INDEX: usize = 3;
+
+// Refuses transactions that call programs outside `allowed_program_ids`, need a signer other
+// than the user or hand the user's token accounts to someone else. Each collection names its
+// own `allowlist_env` variable, whose comma-separated programs are allowed on top
+pub fn check_policy(
+    transaction: &Transaction,
+    user: &Pubkey,
+    allowed_program_ids: &[Pubkey],
+    allowlist_env: &str,
+) -> Result<()> {
+    let mut allowed_program_ids = allowed_program_ids.to_vec();
+
+    if let Ok(program_ids) = env::var(allowlist_env) {
+        for program_id in program_ids.split(',').filter(|id| !id.trim().is_empty()) {
+            allowed_program_ids.push(
+                Pubkey::from_str(program_id.trim())
+                    .or_else(|_| Err(Error::from(ActionError::InvalidProgramAllowlist)))?,
+            );
+        }
+    }
+
+    match policy_violation(transaction, user, &allowed_program_ids) {
+        Some(violation) => {
+            println!("Policy violation: {}", violation);
+            Err(Error::from(ActionError::TransactionPolicyViolation))
+        }
+        None => Ok(()),
+    }
+}
+
+fn policy_violation(
+    transaction: &Transaction,
+    user: &Pubkey,
+    allowed_program_ids: &[Pubkey],
+) -> Option<String> {
+    let message = &transaction.message;
+
+    if message.header.num_required_signatures != 1 || message.account_keys.first() != Some(user) {
+        return Some(format!(
+            "{} required signers, fee payer {:?}",
+            message.header.num_required_signatures,
+            message.account_keys.first()
+        ));
+    }
+
+    for (index, instruction) in message.instructions.iter().enumerate() {
+        let program_id = match message.program_id(index) {
+            Some(program_id) => program_id,
+            None => return Some(format!("instruction {} has no program", index)),
+        };
+
+        if *program_id != compute_budget::id() && !allowed_program_ids.contains(program_id) {
+            return Some(format!("instruction {} calls {}", index, program_id));
+        }
+
+        if *program_id != TOKEN_PROGRAM_ID && *program_id != TOKEN_2022_PROGRAM_ID {
+            continue;
+        }
+
+        let user_account_index = match instruction.data.first() {
+            Some(&APPROVE_INSTRUCTION) => APPROVE_OWNER_INDEX,
+            Some(&SET_AUTHORITY_INSTRUCTION) => SET_AUTHORITY_CURRENT_AUTHORITY_INDEX,
+            Some(&APPROVE_CHECKED_INSTRUCTION) => APPROVE_CHECKED_OWNER_INDEX,
+            _ => continue,
+        };
+
+        let user_account = instruction
+            .accounts
+            .get(user_account_index)
+            .and_then(|account_index| message.account_keys.get(*account_index as usize));
+
+        if user_account == Some(user) {
+            return Some(format!(
+                "instruction {} delegates or changes an authority of the user",
+                index
+            ));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction,
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        system_instruction, system_program,
+    };
+
+    fn transaction(instructions: &[Instruction], payer: &Pubkey) -> Transaction {
+        Transaction::new_unsigned(Message::new(instructions, Some(payer)))
+    }
+
+    fn token_instruction(
+        program_id: Pubkey,
+        data: &[u8],
+        accounts: &[Pubkey],
+        signer: &Pubkey,
+    ) -> Instruction {
+        Instruction::new_with_bytes(
+            program_id,
+            data,
+            accounts
+                .iter()
+                .map(|account| AccountMeta::new(*account, account == signer))
+                .collect(),
+        )
+    }
+
+    const ALLOWED: [Pubkey; 2] = [system_program::ID, TOKEN_PROGRAM_ID];
+
+    #[test]
+    fn accepts_a_plain_payment() {
+        let user = Pubkey::new_unique();
+        let transaction = transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                system_instruction::transfer(&user, &Pubkey::new_unique(), 1_000),
+            ],
+            &user,
+        );
+
+        assert!(policy_violation(&transaction, &user, &ALLOWED).is_none());
+    }
+
+    #[test]
+    fn rejects_programs_outside_the_allowlist() {
+        let user = Pubkey::new_unique();
+        let transaction = transaction(
+            &[Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[],
+                vec![AccountMeta::new(user, true)],
+            )],
+            &user,
+        );
+
+        assert!(policy_violation(&transaction, &user, &ALLOWED).is_some());
+    }
+
+    #[test]
+    fn rejects_another_fee_payer_or_extra_signers() {
+        let user = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let paid_by_other = transaction(&[system_instruction::transfer(&user, &other, 1)], &other);
+        assert!(policy_violation(&paid_by_other, &user, &ALLOWED).is_some());
+
+        let signed_by_other = transaction(&[system_instruction::transfer(&other, &user, 1)], &user);
+        assert!(policy_violation(&signed_by_other, &user, &ALLOWED).is_some());
+    }
+
+    #[test]
+    fn rejects_handing_over_the_users_authority() {
+        let user = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let set_authority = token_instruction(
+            TOKEN_2022_PROGRAM_ID,
+            &[SET_AUTHORITY_INSTRUCTION, 2, 1],
+            &[token_account, user],
+            &user,
+        );
+
+        let transaction = transaction(&[set_authority], &user);
+
+        assert!(policy_violation(&transaction, &user, &[TOKEN_2022_PROGRAM_ID]).is_some());
+    }
+
+    #[test]
+    fn rejects_delegating_the_users_tokens() {
+        let user = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+
+        let approve = token_instruction(
+            TOKEN_PROGRAM_ID,
+            &[APPROVE_INSTRUCTION, 255, 255, 255, 255, 255, 255, 255, 255],
+            &[source, delegate, user],
+            &user,
+        );
+        let approve_checked = token_instruction(
+            TOKEN_PROGRAM_ID,
+            &[APPROVE_CHECKED_INSTRUCTION, 255, 255, 255, 255, 255, 255, 255, 255, 6],
+            &[source, mint, delegate, user],
+            &user,
+        );
+
+        for instruction in [approve, approve_checked] {
+            let transaction = transaction(&[instruction], &user);
+
+            assert!(policy_violation(&transaction, &user, &ALLOWED).is_some());
+        }
+    }
+
+    #[test]
+    fn allows_other_token_instructions() {
+        let user = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let transfer = token_instruction(
+            TOKEN_PROGRAM_ID,
+            &[3, 1, 0, 0, 0, 0, 0, 0, 0],
+            &[source, destination, user],
+            &user,
+        );
+        // An approval of an account the user doesn't own can't give the user's tokens away
+        let approve = token_instruction(
+            TOKEN_PROGRAM_ID,
+            &[APPROVE_INSTRUCTION, 1, 0, 0, 0, 0, 0, 0, 0],
+            &[source, destination, Pubkey::new_unique()],
+            &user,
+        );
+
+        let transaction = transaction(&[transfer, approve], &user);
+
+        assert!(policy_violation(&transaction, &user, &ALLOWED).is_none());
+    }
+}
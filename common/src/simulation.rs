@@ -0,0 +1,292 @@
+use solana_client::{
+    client_error::Result as ClientResult, nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcSimulateTransactionConfig, rpc_response::RpcSimulateTransactionResult,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+use std::{env, str::FromStr};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+use crate::programs::{JUPITER_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+// SystemError::AccountAlreadyInUse and SystemError::ResultWithNegativeLamports
+const SYSTEM_ACCOUNT_ALREADY_IN_USE: u32 = 0;
+const SYSTEM_INSUFFICIENT_LAMPORTS: u32 = 1;
+// TokenError::InsufficientFunds, shared by Token and Token-2022
+const TOKEN_INSUFFICIENT_FUNDS: u32 = 1;
+// Jupiter's SlippageToleranceExceeded
+const JUPITER_SLIPPAGE_TOLERANCE_EXCEEDED: u32 = 6001;
+
+// Signatures are skipped since the payer hasn't signed yet
+pub async fn simulate(
+    client: &RpcClient,
+    transaction: &Transaction,
+) -> ClientResult<RpcSimulateTransactionResult> {
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(CommitmentConfig::confirmed()),
+        ..RpcSimulateTransactionConfig::default()
+    };
+
+    client
+        .simulate_transaction_with_config(transaction, config)
+        .await
+        .map(|response| response.value)
+}
+
+// Runs the transaction before it reaches the wallet when SIMULATE_TRANSACTIONS is enabled, so
+// common failures come back as readable errors
+pub async fn check_simulation(transaction: &Transaction, rpc_url: &str) -> Result<()> {
+    let enabled = env::var("SIMULATE_TRANSACTIONS")
+        .map(|enabled| enabled == "true" || enabled == "1")
+        .unwrap_or(false);
+
+    if !enabled {
+        return Ok(());
+    }
+
+    let client = RpcClient::new(rpc_url.to_string());
+
+    // An unreachable RPC shouldn't block a transaction that may well be fine
+    let result = match simulate(&client, transaction).await {
+        Ok(result) => result,
+        Err(err) => {
+            println!("Error simulating transaction: {}", err);
+            return Ok(());
+        }
+    };
+
+    match result.err {
+        Some(err) => {
+            println!("Simulation failed: {:?}, logs: {:?}", err, result.logs);
+            let logs = result.logs.as_deref().unwrap_or_default();
+
+            Err(Error::from(simulation_error(transaction, &err, logs)))
+        }
+        None => Ok(()),
+    }
+}
+
+// The runtime reports the top-level instruction, so an error raised in a CPI, e.g. the token
+// transfer inside a Jupiter swap, is attributed to the program that failed first in the logs
+fn simulation_error(
+    transaction: &Transaction,
+    err: &TransactionError,
+    logs: &[String],
+) -> ActionError {
+    match err {
+        TransactionError::InsufficientFundsForFee
+        | TransactionError::InsufficientFundsForRent { .. } => ActionError::InsufficientFunds,
+        TransactionError::InstructionError(_, InstructionError::InsufficientFunds) => {
+            ActionError::InsufficientFunds
+        }
+        TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+            let program_id = failing_program(logs)
+                .or_else(|| transaction.message.program_id(*index as usize).copied());
+
+            match (program_id.as_ref(), *code) {
+                (Some(program_id), SYSTEM_ACCOUNT_ALREADY_IN_USE)
+                    if *program_id == system_program::id() =>
+                {
+                    ActionError::AccountAlreadyInUse
+                }
+                (Some(program_id), SYSTEM_INSUFFICIENT_LAMPORTS)
+                    if *program_id == system_program::id() =>
+                {
+                    ActionError::InsufficientFunds
+                }
+                (Some(program_id), TOKEN_INSUFFICIENT_FUNDS)
+                    if *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID =>
+                {
+                    ActionError::InsufficientFunds
+                }
+                (Some(program_id), JUPITER_SLIPPAGE_TOLERANCE_EXCEEDED)
+                    if *program_id == JUPITER_PROGRAM_ID =>
+                {
+                    ActionError::SlippageExceeded
+                }
+                _ => ActionError::SimulationFailed,
+            }
+        }
+        _ => ActionError::SimulationFailed,
+    }
+}
+
+// The innermost failing program logs `Program <id> failed: <error>` before its callers do
+fn failing_program(logs: &[String]) -> Option<Pubkey> {
+    logs.iter().find_map(|log| {
+        let (program, _error) = log.strip_prefix("Program ")?.split_once(" failed: ")?;
+
+        Pubkey::from_str(program).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::Message,
+        pubkey,
+    };
+
+    const SQUADS_PROGRAM_ID: Pubkey = pubkey!("SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu");
+    const KAMINO_FARMS_PROGRAM_ID: Pubkey = pubkey!("FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr");
+
+    // A compute budget instruction followed by a call into `program_id`
+    fn transaction(program_id: Pubkey) -> Transaction {
+        let payer = Pubkey::new_unique();
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+            Instruction::new_with_bytes(program_id, &[0], vec![]),
+        ];
+
+        Transaction::new_unsigned(Message::new(&instructions, Some(&payer)))
+    }
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn custom_error(code: u32) -> TransactionError {
+        TransactionError::InstructionError(1, InstructionError::Custom(code))
+    }
+
+    #[test]
+    fn maps_insufficient_funds_in_a_jupiter_swap() {
+        let logs = logs(&[
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]",
+            "Program ComputeBudget111111111111111111111111111111 success",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 invoke [1]",
+            "Program log: Instruction: Route",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+            "Program log: Instruction: TransferChecked",
+            "Program log: Error: insufficient funds",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 4381 of 1358209 compute units",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 consumed 41619 of 1395000 compute units",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 failed: custom program error: 0x1",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(JUPITER_PROGRAM_ID), &custom_error(1), &logs),
+            ActionError::InsufficientFunds
+        ));
+    }
+
+    #[test]
+    fn maps_insufficient_funds_in_a_kamino_stake() {
+        let logs = logs(&[
+            "Program FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr invoke [1]",
+            "Program log: Instruction: Stake",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]",
+            "Program log: Instruction: Transfer",
+            "Program log: Error: insufficient funds",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 2913 of 181354 compute units",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA failed: custom program error: 0x1",
+            "Program FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr consumed 21559 of 200000 compute units",
+            "Program FarmsPZpWu9i7Kky8tPN37rs2TpmMrAZrC7S7vJa91Hr failed: custom program error: 0x1",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(KAMINO_FARMS_PROGRAM_ID), &custom_error(1), &logs),
+            ActionError::InsufficientFunds
+        ));
+    }
+
+    #[test]
+    fn maps_an_account_already_in_use_by_a_squads_multisig() {
+        let logs = logs(&[
+            "Program SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu invoke [1]",
+            "Program log: Instruction: Create",
+            "Program 11111111111111111111111111111111 invoke [2]",
+            "Allocate: account Address { address: 9mR1hNbdkqnvMRFpTt1RqHXv5TaFr2PXmqEwAMRG3Ueo, base: None } already in use",
+            "Program 11111111111111111111111111111111 failed: custom program error: 0x0",
+            "Program SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu consumed 12209 of 200000 compute units",
+            "Program SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu failed: custom program error: 0x0",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(SQUADS_PROGRAM_ID), &custom_error(0), &logs),
+            ActionError::AccountAlreadyInUse
+        ));
+    }
+
+    #[test]
+    fn maps_jupiter_slippage() {
+        let logs = logs(&[
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 invoke [1]",
+            "Program log: Instruction: SharedAccountsRoute",
+            "Program log: AnchorError occurred. Error Code: SlippageToleranceExceeded. Error Number: 6001. Error Message: Slippage tolerance exceeded.",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 consumed 80000 of 1400000 compute units",
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 failed: custom program error: 0x1771",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(JUPITER_PROGRAM_ID), &custom_error(6001), &logs),
+            ActionError::SlippageExceeded
+        ));
+    }
+
+    #[test]
+    fn maps_a_system_transfer_short_of_lamports() {
+        let logs = logs(&[
+            "Program 11111111111111111111111111111111 invoke [1]",
+            "Transfer: insufficient lamports 1000, need 5000",
+            "Program 11111111111111111111111111111111 failed: custom program error: 0x1",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(system_program::id()), &custom_error(1), &logs),
+            ActionError::InsufficientFunds
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_the_top_level_program_without_logs() {
+        assert!(matches!(
+            simulation_error(&transaction(TOKEN_2022_PROGRAM_ID), &custom_error(1), &[]),
+            ActionError::InsufficientFunds
+        ));
+    }
+
+    #[test]
+    fn keeps_unknown_errors_generic() {
+        // Code 1 from a program other than Token or System means something else entirely
+        let logs = logs(&[
+            "Program SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu invoke [1]",
+            "Program SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu failed: custom program error: 0x1",
+        ]);
+
+        assert!(matches!(
+            simulation_error(&transaction(SQUADS_PROGRAM_ID), &custom_error(1), &logs),
+            ActionError::SimulationFailed
+        ));
+        assert!(matches!(
+            simulation_error(
+                &transaction(JUPITER_PROGRAM_ID),
+                &TransactionError::AccountNotFound,
+                &[]
+            ),
+            ActionError::SimulationFailed
+        ));
+    }
+
+    #[test]
+    fn maps_fee_payer_shortfalls() {
+        assert!(matches!(
+            simulation_error(
+                &transaction(JUPITER_PROGRAM_ID),
+                &TransactionError::InsufficientFundsForFee,
+                &[]
+            ),
+            ActionError::InsufficientFunds
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, instruction::Instruction, message::Message,
+    pubkey::Pubkey, transaction::Transaction,
+};
+use znap::prelude::*;
+
+use crate::errors::ActionError;
+
+pub struct FinalizedTransaction {
+    pub transaction: Transaction,
+    // Not returned to the client, znap's ActionTransaction has no field for it, only logged
+    pub last_valid_block_height: u64,
+}
+
+impl FinalizedTransaction {
+    pub fn into_action_transaction(self, message: &str) -> ActionTransaction {
+        println!(
+            "Returning transaction valid until block height {}",
+            self.last_valid_block_height
+        );
+
+        ActionTransaction {
+            transaction: self.transaction,
+            message: Some(message.to_string()),
+        }
+    }
+}
+
+// Sets the fee payer and a recent blockhash so the transaction is ready to sign as returned
+pub async fn finalize_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    rpc_url: &str,
+) -> Result<FinalizedTransaction> {
+    let client = RpcClient::new(rpc_url.to_string());
+
+    let (recent_blockhash, last_valid_block_height) = client
+        .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+        .await
+        .or_else(|_| Err(Error::from(ActionError::ErrorObtainingRecentBlockhash)))?;
+
+    let message = Message::new_with_blockhash(instructions, Some(payer), &recent_blockhash);
+    let transaction = Transaction::new_unsigned(message);
+
+    Ok(FinalizedTransaction {
+        transaction,
+        last_valid_block_height,
+    })
+}